@@ -1,21 +1,42 @@
 use std::ops::Add;
 use std::{thread, time};
 
-use chrono;
 use colored::*;
-use term_size;
-use scrawl;
 
 use super::util::*;
-
-use timers;
+use crate::output;
+
+/// What to do with the task being logged when starting another one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum StopPolicy {
+    Ask,
+    Stop,
+    Keep,
+}
 
 pub fn log_command(matches: &clap::ArgMatches) {
+    let policy = if matches.is_present("yes") {
+        StopPolicy::Stop
+    } else if matches.is_present("no-stop") {
+        StopPolicy::Keep
+    } else {
+        StopPolicy::Ask
+    };
+
+    start_task(matches, policy);
+}
+
+/// Stops the current task and starts the new one at the same time, without asking.
+pub fn switch_command(matches: &clap::ArgMatches) {
+    start_task(matches, StopPolicy::Stop);
+}
+
+fn start_task(matches: &clap::ArgMatches, policy: StopPolicy) {
     // Cannot panic as the argument parser already ensures it exist
     let task = matches.value_of("TASK").unwrap();
 
-    if task.len() == 0 {
-        println!("Cannot create empty task.");
+    if task.is_empty() {
+        return output::usage_error("Cannot create empty task.");
     }
 
     let time = match matches.value_of("AT") {
@@ -26,56 +47,101 @@ pub fn log_command(matches: &clap::ArgMatches) {
         None => chrono::Utc::now(),
     };
 
-    if !confirm_stop_current(time) {
+    let raw_tags: Vec<&str> = matches.values_of("TAGS").into_iter().flatten().collect();
+    if let Some(raw_tag) = raw_tags.iter().find(|raw_tag| !raw_tag.starts_with('+')) {
+        return output::usage_error(&format!(
+            "Tags must start with '+', quote the task name if it contains spaces: '{}'",
+            raw_tag
+        ));
+    }
+    let tags = match parse_tags(raw_tags.into_iter()) {
+        Some(tags) => tags,
+        None => return,
+    };
+
+    let project = match matches.value_of("project").map(timers::Project::parse) {
+        Some(Ok(project)) => Some(project),
+        Some(Err(err)) => return output::usage_error(&err.to_string()),
+        None => None,
+    };
+
+    let options = timers::LogOptions {
+        note: matches.value_of("note").map(String::from),
+        tags,
+        project,
+    };
+
+    let target = match log_target(task, matches.is_present("new")) {
+        Some(target) => target,
+        None => return,
+    };
+
+    if !confirm_stop_current(policy) {
         return;
     }
 
-    if task.starts_with('@') {
-        // This will strip multiple @ if present,
-        // currently it's not worth to fix this behavior
-        let task_id_result = task.trim_start_matches('@').parse::<u32>();
-
-        match task_id_result {
-            Ok(task_id) => match timers::log_task_at(task_id, time) {
-                Ok(task) => print_status(&task),
-                Err(err) => println!("Error logging on task: {}", err),
-            },
-            Err(_) => println!("'{}' is an invalid task ID", task),
-        };
-    } else {
-        match timers::create_log_task_at(task, time) {
+    match target {
+        Picked::Task(task_id) => match timers::log_task_at_with(task_id, time, &options) {
             Ok(task) => print_status(&task),
-            Err(err) => println!("Error creating task: {}", err),
-        }
+            Err(err) => output::fail_with("Error logging on task", &err),
+        },
+        Picked::New => match timers::create_log_task_at_with(task, time, &options) {
+            Ok(task) => print_status(&task),
+            Err(err) => output::fail_with("Error creating task", &err),
+        },
     }
 }
 
-fn confirm_stop_current(time: chrono::DateTime<chrono::Utc>) -> bool {
+fn confirm_stop_current(policy: StopPolicy) -> bool {
     match timers::get_current_log_task() {
+        // Starting to work again ends the break, there is nothing to keep
+        Ok(Some(task)) if task.is_break => {
+            output::note("Ending the break");
+            true
+        }
         Ok(Some(task)) => {
-            println!(
-                "Currently logging on task {} {}",
+            let current = format!(
+                "task {} {}",
                 format!("@{}:", task.id).yellow().bold(),
                 task.name.red().bold(),
             );
-            let answer = user_input("Do you want to start the new task? [y/n] ");
-
-            if answer.trim() == "n" || answer.trim() == "no" {
-                println!("aborting");
-                false
-            } else {
-                match timers::stop_current_task_at(time) {
-                    Err(err) => {
-                        println!("Error stopping current task: {}", err);
+
+            match policy {
+                StopPolicy::Stop => {
+                    // The current task is stopped when the new one starts
+                    output::note(&format!("Stopping {}", current));
+                    true
+                }
+                StopPolicy::Keep => {
+                    output::fail(
+                        output::EXIT_FAILURE,
+                        &format!("Currently logging on {}, not starting a new task", current),
+                    );
+                    false
+                }
+                StopPolicy::Ask if is_interactive() => {
+                    output::note(&format!("Currently logging on {}", current));
+                    let answer = user_input("Do you want to start the new task? [y/n] ");
+
+                    if answer.trim() == "n" || answer.trim() == "no" {
+                        output::fail(output::EXIT_FAILURE, "aborting");
                         false
+                    } else {
+                        true
                     }
-                    // stopped current, continue with new
-                    _ => true,
+                }
+                StopPolicy::Ask => {
+                    output::usage_error(&format!(
+                        "Currently logging on {}. Cannot ask whether to stop it without a \
+                            terminal, use --yes to stop it or --no-stop to keep it.",
+                        current
+                    ));
+                    false
                 }
             }
         }
         Err(err) => {
-            println!("Error finding current task: {}", err);
+            output::fail_with("Error finding current task", &err);
             false
         }
         // no current task, continue with new
@@ -87,36 +153,30 @@ pub fn status_command(matches: &clap::ArgMatches) {
     let minutes = match matches.value_of("watch") {
         Some(val) => match parse_float(val) {
             Ok(val) => val,
-            Err(_) => {
-                println!("Invalid watch interval '{}'", val);
-                return;
-            }
+            Err(_) => return output::usage_error(&format!("Invalid watch interval '{}'", val)),
         },
         None => 1.,
     } as f64;
 
     loop {
         // clear screen
-        if matches.occurrences_of("watch") != 0 {
+        if matches.occurrences_of("watch") != 0 && output::is_text() {
             print!("\x1B[H\x1B[2J\r");
         }
 
         if matches.is_present("timeline") {
-            let start = chrono::Local::today()
-                .and_hms(0, 0, 0)
-                .with_timezone(&chrono::Utc);
-            let end = chrono::Local::today()
-                .and_hms(0, 0, 0)
-                .add(chrono::Duration::days(1))
-                .with_timezone(&chrono::Utc);
+            let today = chrono::Local::now().date_naive();
+            let start = day_start(today);
+            let end = start.add(chrono::Duration::days(1));
             print_timeline(start, end);
         } else {
             match timers::get_current_log_task() {
                 Ok(task) => match task {
                     Some(task) => print_status(&task),
-                    None => print!("You are not logging on any task."),
+                    None if output::is_text() => print!("You are not logging on any task."),
+                    None => output::print_no_record(),
                 },
-                Err(err) => print!("Error finding current task: {}", err),
+                Err(err) => output::fail_with("Error finding current task", &err),
             };
         }
 
@@ -141,13 +201,18 @@ fn print_timeline(
 ) {
     let mut logs = match timers::get_all_logs_between(start, end) {
         Ok(logs) => logs,
-        Err(err) => {
-            println!("Error while retrieving logs: {}", err);
-            return;
-        }
+        Err(err) => return output::fail_with("Error while retrieving logs", &err),
     };
 
-    if logs.len() == 0 {
+    if !output::is_text() {
+        let records: Vec<output::Record> = logs
+            .iter()
+            .map(|(task, log)| output::log_record(task, None, log))
+            .collect();
+        return output::print_records(&records);
+    }
+
+    if logs.is_empty() {
         println!("There are no logs.");
         return;
     }
@@ -164,7 +229,7 @@ fn print_timeline(
 
         let print_size = cumulative as i32 - printed_size;
 
-        print_timeline_log(&task.name, print_size, log.start, log.end());
+        print_timeline_log(task, print_size, log.start, log.end());
 
         printed_size += print_size;
     }
@@ -173,14 +238,14 @@ fn print_timeline(
 fn clip_logs(
     start: chrono::DateTime<chrono::Utc>,
     end: chrono::DateTime<chrono::Utc>,
-    logs: &mut Vec<(timers::Task, timers::Log)>
+    logs: &mut [(timers::Task, timers::Log)]
 ) -> Result<(), timers::Error> {
-    if logs.len() == 0 {
+    if logs.is_empty() {
         return Err(timers::Error::Value(timers::ValueError::new("There are no logs.")));
     }
 
     {
-        let mut first = logs.first_mut().unwrap();
+        let first = logs.first_mut().unwrap();
         let logs_start = first.1.start;
 
         if logs_start < start {
@@ -189,7 +254,7 @@ fn clip_logs(
     }
 
     {
-        let mut last = logs.last_mut().unwrap();
+        let last = logs.last_mut().unwrap();
         let logs_end = last.1.end();
 
         if logs_end > end {
@@ -200,8 +265,8 @@ fn clip_logs(
     Ok(())
 }
 
-fn compute_unit(logs: &Vec<(timers::Task, timers::Log)>) -> Result<f64, timers::Error> {
-    if logs.len() == 0 {
+fn compute_unit(logs: &[(timers::Task, timers::Log)]) -> Result<f64, timers::Error> {
+    if logs.is_empty() {
         return Err(timers::Error::Value(timers::ValueError::new("There are no logs.")));
     }
 
@@ -211,55 +276,64 @@ fn compute_unit(logs: &Vec<(timers::Task, timers::Log)>) -> Result<f64, timers::
     let timespan = end - start;
     let height = get_term_height() - 1.;
 
-    let unit = height as f64 / timespan.num_seconds() as f64;
+    let unit = height / timespan.num_seconds() as f64;
 
     Ok(unit)
 }
 
+// Breaks are drawn with hollow and dotted marks, to stand out from work
 fn print_timeline_log(
-    name: &str,
+    task: &timers::Task,
     size: i32,
     start: chrono::DateTime<chrono::Utc>,
     end: chrono::DateTime<chrono::Utc>,
 ) {
+    let (open, line, close) = if task.is_break { ("○", "┊", "●") } else { ("◇", "|", "◆") };
+    let name = if task.is_break {
+        task.name.dimmed().italic()
+    } else {
+        task.name.bold()
+    };
     let duration = timers::format_duration(end - start);
     let start = start.with_timezone(&chrono::Local)
-        .format("%H:%M").to_string();
+        .format(config().time_format()).to_string();
     let end = end.with_timezone(&chrono::Local)
-        .format("%H:%M").to_string();
+        .format(config().time_format()).to_string();
     match size {
         0 => println!(
-            " ◇ {} -> {} {} [{}]",
+            " {} {} -> {} {} [{}]",
+            open,
             start,
             end,
-            name.bold(),
+            name,
             duration,
         ),
         1 => println!(
-            " ◇ {} -> {} {} [{}]",
+            " {} {} -> {} {} [{}]",
+            open,
             start,
             end,
-            name.bold(),
+            name,
             duration,
         ),
         2 => {
-            println!(" ◇ {} {} [{}]", start, name.bold(), duration);
-            println!(" | {}", end);
+            println!(" {} {} {} [{}]", open, start, name, duration);
+            println!(" {} {}", line, end);
         }
         3 => {
-            println!(" ◇ {}", start);
-            println!(" | {} [{}]", name.bold(), duration);
-            println!(" ◆ {}", end);
+            println!(" {} {}", open, start);
+            println!(" {} {} [{}]", line, name, duration);
+            println!(" {} {}", close, end);
         }
         n => {
             println!("{} {}", n, size);
-            println!(" ◇ {}", start);
-            println!(" | {}", name.bold());
-            println!(" | {}", duration);
+            println!(" {} {}", open, start);
+            println!(" {} {}", line, name);
+            println!(" {} {}", line, duration);
             for _ in 0..(n - 4) {
-                println!(" |");
+                println!(" {}", line);
             }
-            println!(" ◆ {}", end);
+            println!(" {} {}", close, end);
         }
     };
 }
@@ -273,24 +347,177 @@ pub fn stop_command(matches: &clap::ArgMatches) {
         None => chrono::Utc::now(),
     };
 
-    match timers::stop_current_task_at(time) {
+    match timers::stop_current_task_at_with_note(time, matches.value_of("note")) {
         Ok(task) => print_status(&task),
-        Err(timers::Error::Value(_)) => {
-            println!("Cannot stop because you're not logging on any task.")
+        Err(err @ timers::Error::Value(_)) => match timers::get_current_log_task() {
+            Ok(None) => output::fail(
+                output::EXIT_FAILURE,
+                "Cannot stop because you're not logging on any task.",
+            ),
+            _ => output::fail_with("Error stopping task", &err),
+        },
+        Err(err) => output::fail_with("Error stopping task", &err),
+    }
+}
+
+pub fn pause_command(matches: &clap::ArgMatches) {
+    let time = match matches.value_of("AT") {
+        Some(raw_time) => match parse_time(raw_time) {
+            Some(time) => time,
+            None => return,
+        },
+        None => chrono::Utc::now(),
+    };
+
+    match timers::pause_at_with_note(time, matches.value_of("note")) {
+        Ok((paused, break_task)) => {
+            output::note(&format!("Paused task @{}: {}", paused.id, paused.name));
+            print_status(&break_task);
         }
-        Err(err) => println!("An stopping task: {}", err),
+        Err(err) => output::fail_with("Error pausing", &err),
     }
 }
 
-pub fn edit_command(matches: &clap::ArgMatches) {
-    let task = matches.value_of("TASK").unwrap();
-    
-    match task.trim_start_matches('@').parse::<u32>() {
-        Ok(task_id) => {
-            let path = timers::task_path(task_id);
-            let path_ref = path.to_str().unwrap();
-            scrawl::editor::new().file(path_ref).edit().open().unwrap();
+pub fn resume_command(matches: &clap::ArgMatches) {
+    let time = match matches.value_of("AT") {
+        Some(raw_time) => match parse_time(raw_time) {
+            Some(time) => time,
+            None => return,
         },
-        Err(_) => println!("'{}' is an invalid task ID", task),
+        None => chrono::Utc::now(),
     };
+
+    match timers::resume_at(time) {
+        Ok(task) => print_status(&task),
+        Err(err) => output::fail_with("Error resuming", &err),
+    }
+}
+
+pub fn note_command(matches: &clap::ArgMatches) {
+    match timers::note_current_log(matches.value_of("NOTE").unwrap()) {
+        Ok(task) => print_status(&task),
+        Err(timers::Error::Value(_)) => output::fail(
+            output::EXIT_FAILURE,
+            "Cannot set a note because you're not logging on any task.",
+        ),
+        Err(err) => output::fail_with("Error setting note", &err),
+    }
 }
+
+pub fn edit_command(matches: &clap::ArgMatches) {
+    let task_id = match resolve_task(matches.value_of("TASK").unwrap()) {
+        Some(task_id) => task_id,
+        None => return,
+    };
+
+    let original = match timers::task_content(task_id) {
+        Ok(original) => original,
+        Err(err) => return output::fail_with("Error reading task", &err),
+    };
+
+    // The task file is edited as a copy, which replaces the real file only once
+    // valid. Otherwise the editor is opened again with the error on top.
+    let mut content = original;
+    loop {
+        let edited = match scrawl::editor::new().contents(&content).open() {
+            Ok(edited) => edited,
+            Err(err) => {
+                let message = format!("Error opening the editor: {}", err);
+                return output::fail(output::EXIT_FAILURE, &message);
+            }
+        };
+
+        if edited.trim().is_empty() {
+            return output::note("Empty task file, the changes were discarded");
+        }
+        if edited == content {
+            return output::note("No changes made");
+        }
+
+        match timers::replace_task(task_id, &edited) {
+            Ok((old_task, task)) => {
+                if let Some(old_task) = old_task.filter(|_| output::is_text()) {
+                    print_task_changes(&old_task, &task);
+                }
+                print_status(&task);
+                return;
+            }
+            Err(err) => content = with_error_comment(&edited, &err),
+        }
+    }
+}
+
+fn with_error_comment(content: &str, err: &timers::Error) -> String {
+    let stripped: Vec<&str> = content.lines().skip_while(|line| line.starts_with('#')).collect();
+    format!(
+        "# {}\n# Fix the task and save, or save an empty file to discard the changes.\n{}\n",
+        err,
+        stripped.join("\n")
+    )
+}
+
+fn print_task_changes(old_task: &timers::Task, task: &timers::Task) {
+    if old_task.name != task.name {
+        println!("name: {} -> {}", old_task.name, task.name);
+    }
+    if format_project(old_task) != format_project(task) {
+        println!("project: {} -> {}", format_project(old_task), format_project(task));
+    }
+    if old_task.tags != task.tags {
+        println!("tags: {} -> {}", format_tags(old_task), format_tags(task));
+    }
+
+    let same_log = |a: &timers::Log, b: &timers::Log| a.start == b.start && a.end == b.end;
+    for log in old_task.logs.iter().filter(|log| !task.logs.iter().any(|other| same_log(log, other))) {
+        println!("{} {} [{}]", "-".red(), format_log(log), timers::format_duration(log.duration()));
+    }
+    for log in task.logs.iter().filter(|log| !old_task.logs.iter().any(|other| same_log(log, other))) {
+        println!("{} {} [{}]", "+".green(), format_log(log), timers::format_duration(log.duration()));
+    }
+}
+
+pub fn add_command(matches: &clap::ArgMatches) {
+    let task_id = match resolve_task(matches.value_of("TASK").unwrap()) {
+        Some(task_id) => task_id,
+        None => return,
+    };
+
+    let start = match parse_time(matches.value_of("from").unwrap()) {
+        Some(start) => start,
+        None => return,
+    };
+    let end = match (matches.value_of("to"), matches.value_of("duration")) {
+        (Some(raw_end), _) => match parse_time(raw_end) {
+            Some(end) => end,
+            None => return,
+        },
+        (None, Some(raw_duration)) => match parse_duration(raw_duration) {
+            Some(duration) => start + duration,
+            None => return,
+        },
+        // Cannot happen as the argument parser requires one of them
+        (None, None) => return,
+    };
+
+    let policy = if matches.is_present("trim") {
+        timers::OverlapPolicy::Trim
+    } else {
+        timers::OverlapPolicy::Reject
+    };
+
+    let mut log = timers::Log::new(start, Some(end));
+    log.note = matches.value_of("note").map(String::from);
+    match timers::add_log(task_id, log, policy) {
+        Ok((task, added)) => {
+            for log in added.iter() {
+                output::note(&format!(
+                    "Added {} [{}]",
+                    format_log(log),
+                    timers::format_duration(log.duration())
+                ));
+            }
+            print_status(&task);
+        }
+        Err(err) => output::fail_with("Error adding log", &err),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,399 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::errors::{Error, ValueError};
+
+const WEEKDAYS: [&str; 7] = [
+    "monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    DateFormat,
+    Bool,
+    Count,
+    Minutes,
+    Time,
+    Weekday,
+    Weekdays,
+    Choice(&'static [&'static str]),
+}
+
+/// A known key of the configuration file, with its default value.
+pub struct Setting {
+    pub key: &'static str,
+    pub default: &'static str,
+    pub help: &'static str,
+    kind: Kind,
+}
+
+pub const SETTINGS: [Setting; 14] = [
+    Setting {
+        key: "display.date_format",
+        default: "%a %b %d %H:%M",
+        help: "Format of the dates of 'timers tasks --long', as in strftime.",
+        kind: Kind::DateFormat,
+    },
+    Setting {
+        key: "display.clock",
+        default: "24h",
+        help: "Show the times of the logs on a 24h or 12h clock.",
+        kind: Kind::Choice(&["24h", "12h"]),
+    },
+    Setting {
+        key: "display.colors",
+        default: "true",
+        help: "Use colours in the terminal.",
+        kind: Kind::Bool,
+    },
+    Setting {
+        key: "week.start",
+        default: "monday",
+        help: "First day of the week, for reports and timesheets.",
+        kind: Kind::Weekday,
+    },
+    Setting {
+        key: "week.work_days",
+        default: "monday,tuesday,wednesday,thursday,friday",
+        help: "Days shown as work days in reports and checked for gaps, the others as weekend.",
+        kind: Kind::Weekdays,
+    },
+    Setting {
+        key: "workday.start",
+        default: "09:00",
+        help: "Start of the working hours, in which 'timers gaps' looks for untracked time.",
+        kind: Kind::Time,
+    },
+    Setting {
+        key: "workday.end",
+        default: "17:00",
+        help: "End of the working hours.",
+        kind: Kind::Time,
+    },
+    Setting {
+        key: "tasks.num",
+        default: "30",
+        help: "Number of tasks printed by 'timers tasks'.",
+        kind: Kind::Count,
+    },
+    Setting {
+        key: "status.watch_interval",
+        default: "5",
+        help: "Minutes between updates of 'timers status --watch'.",
+        kind: Kind::Minutes,
+    },
+    Setting {
+        key: "rounding.mode",
+        default: "none",
+        help: "How to round the hours of timesheets.",
+        kind: Kind::Choice(&["none", "nearest", "up", "down"]),
+    },
+    Setting {
+        key: "rounding.increment",
+        default: "15",
+        help: "Round timesheet hours to multiples of this many minutes.",
+        kind: Kind::Choice(&["6", "15", "30"]),
+    },
+    Setting {
+        key: "rounding.per",
+        default: "day",
+        help: "Round each log separately, or the total of each task in each day.",
+        kind: Kind::Choice(&["log", "day"]),
+    },
+    Setting {
+        key: "breaks.minimum",
+        default: "30",
+        help: "Minutes of break required in a day of work, 0 to disable the check.",
+        kind: Kind::Count,
+    },
+    Setting {
+        key: "breaks.required_after",
+        default: "6",
+        help: "Hours of work in a day from which the minimum break is required.",
+        kind: Kind::Count,
+    },
+];
+
+// Aliases are the keys of their own table, as in aliases.st = "status"
+const ALIASES: &str = "aliases";
+
+/// The settings of the configuration file, with the defaults for the missing ones.
+#[derive(Debug, Clone)]
+pub struct Config {
+    values: BTreeMap<&'static str, String>,
+    aliases: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            values: SETTINGS
+                .iter()
+                .map(|setting| (setting.key, setting.default.to_string()))
+                .collect(),
+            aliases: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Config, Error> {
+        let path = config_path();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let document = read_document()?;
+        let mut config = Config::default();
+        for setting in SETTINGS.iter() {
+            let (section, name) = setting.key.split_once('.').unwrap();
+            if let Some(item) = document.get(section).and_then(|table| table.get(name)) {
+                let raw = item_text(item)
+                    .ok_or_else(|| invalid_value(setting.key, item.to_string().trim()))?;
+                config.values.insert(setting.key, parse_value(setting, &raw)?);
+            }
+        }
+
+        if let Some(aliases) = document.get(ALIASES).and_then(|item| item.as_table_like()) {
+            for (name, item) in aliases.iter() {
+                let command = item
+                    .as_str()
+                    .ok_or_else(|| invalid_value(&alias_key(name), item.to_string().trim()))?;
+                config.aliases.insert(name.to_string(), command.to_string());
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The value of a setting or alias, as written with `timers config set`.
+    pub fn get(&self, key: &str) -> Result<&str, Error> {
+        if let Some(value) = self.values.get(key) {
+            return Ok(value);
+        }
+
+        match key.strip_prefix("aliases.") {
+            Some(name) => self.alias(name).ok_or_else(|| unknown_key(key)),
+            None => Err(unknown_key(key)),
+        }
+    }
+
+    /// All settings and aliases with their values.
+    pub fn list(&self) -> Vec<(String, String)> {
+        let settings = self.values.iter().map(|(key, value)| (key.to_string(), value.clone()));
+        let aliases = self.aliases.iter().map(|(name, command)| (alias_key(name), command.clone()));
+        settings.chain(aliases).collect()
+    }
+
+    pub fn alias(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(|command| command.as_str())
+    }
+
+    pub fn date_format(&self) -> &str {
+        &self.values["display.date_format"]
+    }
+
+    /// Format of a time of the day, following `display.clock`.
+    pub fn time_format(&self) -> &str {
+        if self.values["display.clock"] == "12h" {
+            "%I:%M %p"
+        } else {
+            "%H:%M"
+        }
+    }
+
+    pub fn colors(&self) -> bool {
+        self.values["display.colors"] == "true"
+    }
+
+    pub fn week_start(&self) -> chrono::Weekday {
+        self.values["week.start"].parse().unwrap()
+    }
+
+    /// The break required on days with at least `break_required_after` of work.
+    pub fn minimum_break(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.values["breaks.minimum"].parse().unwrap())
+    }
+
+    pub fn break_required_after(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.values["breaks.required_after"].parse().unwrap())
+    }
+
+    /// The start and end of the working hours, in local time.
+    pub fn workday_hours(&self) -> (chrono::NaiveTime, chrono::NaiveTime) {
+        let parse = |key| chrono::NaiveTime::parse_from_str(&self.values[key], "%H:%M").unwrap();
+        (parse("workday.start"), parse("workday.end"))
+    }
+
+    pub fn is_work_day(&self, day: chrono::Weekday) -> bool {
+        self.values["week.work_days"]
+            .split(',')
+            .any(|work_day| work_day.parse::<chrono::Weekday>() == Ok(day))
+    }
+}
+
+/// Changes a setting or alias in the configuration file, keeping the rest of it as it is.
+pub fn set_config(key: &str, raw_value: &str) -> Result<(), Error> {
+    let mut document = if config_path().exists() {
+        read_document()?
+    } else {
+        toml_edit::DocumentMut::new()
+    };
+
+    if let Some(name) = key.strip_prefix("aliases.") {
+        if name.is_empty() || raw_value.trim().is_empty() {
+            return Err(invalid_value(key, raw_value));
+        }
+        section_table(&mut document, ALIASES).insert(name, toml_edit::value(raw_value));
+    } else {
+        let setting = SETTINGS
+            .iter()
+            .find(|setting| setting.key == key)
+            .ok_or_else(|| unknown_key(key))?;
+        let (section, name) = key.split_once('.').unwrap();
+        let value = toml_value(setting, &parse_value(setting, raw_value)?);
+        section_table(&mut document, section).insert(name, value);
+    }
+
+    let path = config_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, document.to_string())?;
+
+    Ok(())
+}
+
+/// The configuration file, `config.toml` in the `timers` configuration
+/// directory unless `TIMERS_CONFIG` is set.
+pub fn config_path() -> PathBuf {
+    match std::env::var_os("TIMERS_CONFIG") {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => {
+            let mut path = dirs::config_dir().unwrap_or_default();
+            path.push("timers");
+            path.push("config.toml");
+            path
+        }
+    }
+}
+
+// Sections are written as [section] tables, even if missing until now
+fn section_table<'a>(
+    document: &'a mut toml_edit::DocumentMut,
+    section: &str,
+) -> &'a mut dyn toml_edit::TableLike {
+    if document.get(section).and_then(|item| item.as_table_like()).is_none() {
+        document.insert(section, toml_edit::table());
+    }
+    document[section].as_table_like_mut().unwrap()
+}
+
+fn read_document() -> Result<toml_edit::DocumentMut, Error> {
+    let path = config_path();
+    std::fs::read_to_string(&path)?.parse().map_err(|err| {
+        Error::Value(ValueError::new(
+            format!("Invalid configuration file {}: {}", path.display(), err).as_str(),
+        ))
+    })
+}
+
+// Lists are read as comma separated values, as they are given to `timers config set`
+fn item_text(item: &toml_edit::Item) -> Option<String> {
+    let value = item.as_value()?;
+    match value {
+        toml_edit::Value::String(text) => Some(text.value().clone()),
+        toml_edit::Value::Boolean(flag) => Some(flag.value().to_string()),
+        toml_edit::Value::Integer(number) => Some(number.value().to_string()),
+        toml_edit::Value::Float(number) => Some(number.value().to_string()),
+        toml_edit::Value::Array(values) => {
+            let texts: Option<Vec<&str>> = values.iter().map(|value| value.as_str()).collect();
+            Some(texts?.join(","))
+        }
+        _ => None,
+    }
+}
+
+fn toml_value(setting: &Setting, value: &str) -> toml_edit::Item {
+    match setting.kind {
+        Kind::Bool => toml_edit::value(value == "true"),
+        Kind::Weekdays => toml_edit::value(value.split(',').collect::<toml_edit::Array>()),
+        _ => match value.parse::<i64>() {
+            Ok(number) if setting.kind != Kind::DateFormat => toml_edit::value(number),
+            _ => match value.parse::<f64>() {
+                Ok(number) if setting.kind == Kind::Minutes => toml_edit::value(number),
+                _ => toml_edit::value(value),
+            },
+        },
+    }
+}
+
+// Checks the value of a setting, returning it in its canonical form
+fn parse_value(setting: &Setting, raw: &str) -> Result<String, Error> {
+    let raw = raw.trim();
+    let parsed = match setting.kind {
+        // Unknown specifiers would make formatting the dates panic
+        Kind::DateFormat => Some(raw.to_string()).filter(|format| {
+            !format.is_empty()
+                && !chrono::format::StrftimeItems::new(format)
+                    .any(|item| item == chrono::format::Item::Error)
+        }),
+        Kind::Bool => match raw {
+            "true" | "yes" | "on" => Some("true".to_string()),
+            "false" | "no" | "off" => Some("false".to_string()),
+            _ => None,
+        },
+        Kind::Count => raw.parse::<u32>().ok().map(|count| count.to_string()),
+        Kind::Minutes => raw
+            .parse::<f64>()
+            .ok()
+            .filter(|minutes| *minutes > 0.)
+            .map(|minutes| minutes.to_string()),
+        Kind::Time => chrono::NaiveTime::parse_from_str(raw, "%H:%M")
+            .ok()
+            .map(|time| time.format("%H:%M").to_string()),
+        Kind::Weekday => parse_weekday(raw),
+        Kind::Weekdays => raw
+            .split(',')
+            .filter(|day| !day.trim().is_empty())
+            .map(parse_weekday)
+            .collect::<Option<Vec<String>>>()
+            .map(|days| days.join(",")),
+        Kind::Choice(choices) => {
+            choices.iter().find(|choice| **choice == raw).map(|choice| choice.to_string())
+        }
+    };
+
+    parsed.ok_or_else(|| match setting.kind {
+        Kind::Choice(choices) => Error::Value(ValueError::new(
+            format!(
+                "Invalid value '{}' for {}, use one of: {}.",
+                raw,
+                setting.key,
+                choices.join(", ")
+            )
+            .as_str(),
+        )),
+        _ => invalid_value(setting.key, raw),
+    })
+}
+
+// Week days are written in full, in lower case
+fn parse_weekday(raw: &str) -> Option<String> {
+    let day = raw.trim().parse::<chrono::Weekday>().ok()?;
+    Some(WEEKDAYS[day.num_days_from_monday() as usize].to_string())
+}
+
+fn alias_key(name: &str) -> String {
+    format!("{}.{}", ALIASES, name)
+}
+
+fn unknown_key(key: &str) -> Error {
+    Error::Value(ValueError::new(
+        format!("Unknown setting '{}', see 'timers config list'.", key).as_str(),
+    ))
+}
+
+fn invalid_value(key: &str, raw: &str) -> Error {
+    Error::Value(ValueError::new(format!("Invalid value '{}' for {}.", raw, key).as_str()))
+}
@@ -0,0 +1,67 @@
+use crate::output;
+
+pub fn config_command(matches: &clap::ArgMatches) {
+    match matches.subcommand() {
+        ("get", Some(submatches)) => {
+            let key = submatches.value_of("KEY").unwrap();
+            match load_config().map(|config| config.get(key).map(|value| value.to_string())) {
+                Some(Ok(value)) if output::is_text() => println!("{}", value),
+                Some(Ok(value)) => output::print_record(&config_record(key, &value)),
+                Some(Err(err)) => output::fail_with("Error reading setting", &err),
+                None => {}
+            }
+        }
+        ("set", Some(submatches)) => {
+            let key = submatches.value_of("KEY").unwrap();
+            let value = submatches.value_of("VALUE").unwrap();
+            match timers::set_config(key, value) {
+                Ok(()) if output::is_text() => {
+                    println!("Set {} in {}.", key, timers::config_path().display())
+                }
+                Ok(()) => print_setting(key),
+                Err(err) => output::fail_with("Error changing setting", &err),
+            }
+        }
+        _ => print_config(),
+    }
+}
+
+// Read again instead of the configuration loaded at startup, so that
+// errors in the file are reported
+fn load_config() -> Option<timers::Config> {
+    match timers::Config::load() {
+        Ok(config) => Some(config),
+        Err(err) => {
+            output::fail_with("Error reading the configuration", &err);
+            None
+        }
+    }
+}
+
+fn print_setting(key: &str) {
+    if let Some(config) = load_config() {
+        output::print_record(&config_record(key, config.get(key).unwrap_or_default()));
+    }
+}
+
+fn print_config() {
+    let config = match load_config() {
+        Some(config) => config,
+        None => return,
+    };
+
+    if !output::is_text() {
+        let records: Vec<output::Record> =
+            config.list().iter().map(|(key, value)| config_record(key, value)).collect();
+        return output::print_records(&records);
+    }
+
+    println!("# {}", timers::config_path().display());
+    for (key, value) in config.list() {
+        println!("{} = {}", key, value);
+    }
+}
+
+fn config_record(key: &str, value: &str) -> output::Record {
+    vec![("key", output::Field::text(key)), ("value", output::Field::text(value))]
+}
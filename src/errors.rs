@@ -27,6 +27,12 @@ impl ValueError {
 pub enum Error {
     Io(std::io::Error),
     Value(ValueError),
+    /// A task file which cannot be parsed.
+    Corrupt {
+        path: std::path::PathBuf,
+        line: usize,
+        reason: String,
+    },
 }
 
 impl From<std::io::Error> for Error {
@@ -46,6 +52,9 @@ impl std::fmt::Display for Error {
         match self {
             Error::Io(err) => err.fmt(f),
             Error::Value(err) => err.fmt(f),
+            Error::Corrupt { path, line, reason } => {
+                write!(f, "Corrupt task file {}, line {}: {}", path.display(), line, reason)
+            }
         }
     }
 }
@@ -55,6 +64,7 @@ impl std::error::Error for Error {
         match self {
             Error::Io(err) => err.source(),
             Error::Value(err) => err.source(),
+            Error::Corrupt { .. } => None,
         }
     }
 }
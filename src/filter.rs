@@ -0,0 +1,25 @@
+use std::collections::HashMap;
+
+use crate::repo::Task;
+
+/// Selects tasks by their tags: a task matches if it has all of `tags`
+/// and none of `not_tags`.
+#[derive(Debug, Default, Clone)]
+pub struct TaskFilter {
+    pub tags: Vec<String>,
+    pub not_tags: Vec<String>,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        self.tags.iter().all(|tag| task.has_tag(tag))
+            && !self.not_tags.iter().any(|tag| task.has_tag(tag))
+    }
+
+    pub fn apply(&self, tasks: HashMap<u32, Task>) -> HashMap<u32, Task> {
+        tasks
+            .into_iter()
+            .filter(|(_, task)| self.matches(task))
+            .collect()
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::errors::Error;
+use crate::repo::{Log, Repo, Task};
+use crate::storage::Storage;
+use crate::validate::describe_log;
+
+/// A problem found in the repository.
+#[derive(Debug)]
+pub struct Issue {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub description: String,
+    /// Whether the problem can be repaired without losing information,
+    /// or was repaired when checking with repair enabled.
+    pub repairable: bool,
+}
+
+impl Issue {
+    fn new(path: &std::path::Path, description: String, repairable: bool) -> Issue {
+        Issue {
+            path: path.to_path_buf(),
+            line: None,
+            description,
+            repairable,
+        }
+    }
+}
+
+/// Checks all task files in the repository. If repair is true, the
+/// repairable problems are fixed, keeping a .bak copy of the changed files.
+pub fn fsck(repo: &Repo, repair: bool) -> Result<Vec<Issue>, Error> {
+    let mut issues = Vec::new();
+
+    let mut tasks: Vec<(Task, bool)> = Vec::new();
+    let mut content_ids = Vec::new();
+    // Files with malformed lines which cannot be dropped safely are
+    // never written back, their other issues are left for the user too
+    let mut kept = HashSet::new();
+    for (file_id, path) in repo.task_files()? {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                issues.push(Issue::new(&path, format!("cannot be read: {}", err), false));
+                continue;
+            }
+        };
+
+        let (mut task, errors) = match Repo::parse_task_lenient(path.clone(), &content) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                issues.push(corrupt_issue(&path, err, false));
+                continue;
+            }
+        };
+
+        // Malformed lines are dropped when the task is written back
+        let mut changed = !errors.is_empty();
+        for err in errors {
+            let droppable = match &err {
+                Error::Corrupt { line, .. } => is_droppable(&content, *line),
+                _ => false,
+            };
+            if !droppable {
+                kept.insert(path.clone());
+            }
+            issues.push(corrupt_issue(&path, err, droppable));
+        }
+
+        content_ids.push((task.id, file_id, path.clone()));
+        if task.id != file_id {
+            issues.push(Issue::new(
+                &path,
+                format!("the file contains the ID @{} instead of @{}", task.id, file_id),
+                true,
+            ));
+            task.id = file_id;
+            changed = true;
+        }
+
+        if task.logs.windows(2).any(|pair| pair[0].start > pair[1].start) {
+            issues.push(Issue::new(&path, "the logs are not in order".to_string(), true));
+            task.logs.sort_by_key(|log| log.start);
+            changed = true;
+        }
+
+        for log in task.logs.iter().filter(|log| log.end.is_some_and(|end| end < log.start)) {
+            issues.push(Issue::new(
+                &path,
+                format!("the log {} ends before it starts", describe_log(log)),
+                false,
+            ));
+        }
+
+        // Only the last log of a task can be open, earlier ones
+        // were closed when the next one started
+        let last = task.logs.len().saturating_sub(1);
+        for i in 0..last {
+            if task.logs[i].end.is_none() {
+                issues.push(Issue::new(
+                    &path,
+                    format!("the log {} is open, but is not the last one", describe_log(&task.logs[i])),
+                    true,
+                ));
+                task.logs[i].end = Some(task.logs[i + 1].start);
+                changed = true;
+            }
+        }
+
+        tasks.push((task, changed));
+    }
+
+    check_duplicate_ids(&content_ids, &mut issues);
+    close_open_logs(&mut tasks, &mut issues);
+    check_overlaps(&tasks, &mut issues);
+    for issue in issues.iter_mut().filter(|issue| kept.contains(&issue.path)) {
+        issue.repairable = false;
+    }
+    issues.sort_by(|a, b| a.path.cmp(&b.path));
+
+    if repair {
+        for (task, changed) in tasks.iter_mut() {
+            if *changed && !kept.contains(&task.path) {
+                let mut backup = task.path.clone().into_os_string();
+                backup.push(".bak");
+                std::fs::copy(&task.path, backup)?;
+
+                task.logging = task.logs.iter().any(|log| log.end.is_none());
+                repo.replace_task(task)?;
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+fn corrupt_issue(path: &std::path::Path, err: Error, repairable: bool) -> Issue {
+    match err {
+        Error::Corrupt { path, line, reason } => Issue {
+            path,
+            line: Some(line),
+            description: reason,
+            repairable,
+        },
+        err => Issue::new(path, err.to_string(), repairable),
+    }
+}
+
+// Only malformed task fields can be dropped, other lines such as
+// logs with an invalid time would lose logged time. Line numbers
+// count from the task ID, as in Repo::parse_task_lenient.
+fn is_droppable(content: &str, line: usize) -> bool {
+    let fields = ["project:", "archived:", "break:"];
+    content
+        .lines()
+        .skip_while(|line| line.starts_with('#'))
+        .nth(line.saturating_sub(1))
+        .is_some_and(|line| fields.iter().any(|field| line.starts_with(field)))
+}
+
+// Duplicate IDs come from files whose ID does not match the file name,
+// which is repaired by taking the ID from the file name
+fn check_duplicate_ids(content_ids: &[(u32, u32, PathBuf)], issues: &mut Vec<Issue>) {
+    let mut files: HashMap<u32, usize> = HashMap::new();
+    for (content_id, _, _) in content_ids {
+        *files.entry(*content_id).or_default() += 1;
+    }
+
+    for (content_id, file_id, path) in content_ids {
+        if files[content_id] > 1 && content_id != file_id {
+            issues.push(Issue::new(
+                path,
+                format!("the ID @{} is also used by another file", content_id),
+                true,
+            ));
+        }
+    }
+}
+
+// Only one task can be logging, older open logs are closed
+// when the next task started, as 'timers log' would have done
+fn close_open_logs(tasks: &mut [(Task, bool)], issues: &mut Vec<Issue>) {
+    let mut open: Vec<(chrono::DateTime<chrono::Utc>, usize)> = tasks
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (task, _))| task.logs.last().filter(|log| log.end.is_none()).map(|log| (log.start, i)))
+        .collect();
+    open.sort();
+
+    for pair in open.windows(2) {
+        let (_, i) = pair[0];
+        let (next_start, next) = pair[1];
+        let next_id = tasks[next].0.id;
+
+        let (task, changed) = &mut tasks[i];
+        let log = task.logs.last_mut().unwrap();
+        issues.push(Issue::new(
+            &task.path,
+            format!("the log {} is open while @{} is also logging", describe_log(log), next_id),
+            true,
+        ));
+        log.end = Some(next_start);
+        *changed = true;
+    }
+}
+
+fn check_overlaps(tasks: &[(Task, bool)], issues: &mut Vec<Issue>) {
+    let mut logs: Vec<(&Log, &Task)> = tasks
+        .iter()
+        .flat_map(|(task, _)| task.logs.iter().map(move |log| (log, task)))
+        .collect();
+    logs.sort_by_key(|(log, _)| log.start);
+
+    // Compare each log with the one ending last among the previous ones
+    let mut latest: Option<(&Log, &Task)> = None;
+    for (log, task) in logs {
+        if let Some((previous, previous_task)) = latest {
+            if previous.overlaps(log) {
+                issues.push(Issue::new(
+                    &task.path,
+                    format!(
+                        "the log {} overlaps the log {} of @{}",
+                        describe_log(log),
+                        describe_log(previous),
+                        previous_task.id
+                    ),
+                    false,
+                ));
+            }
+            if previous.end() >= log.end() {
+                continue;
+            }
+        }
+        latest = Some((log, task));
+    }
+}
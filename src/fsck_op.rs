@@ -0,0 +1,80 @@
+use colored::*;
+
+use crate::output;
+
+pub fn fsck_command(matches: &clap::ArgMatches) {
+    let repair = matches.is_present("repair");
+
+    match timers::storage_kind() {
+        Ok(timers::StorageKind::Text) => {}
+        Ok(_) => {
+            return output::usage_error(
+                "fsck only works with plain text storage, see 'timers migrate'.",
+            )
+        }
+        Err(err) => return output::fail_with("Error checking tasks", &err),
+    }
+
+    let issues = match timers::fsck(repair) {
+        Ok(issues) => issues,
+        Err(err) => return output::fail_with("Error checking tasks", &err),
+    };
+
+    // Problems left in the tasks make fsck fail, for scripts checking them
+    if issues.iter().any(|issue| !(issue.repairable && repair)) {
+        output::set_exit_code(output::EXIT_FAILURE);
+    }
+
+    if !output::is_text() {
+        let records: Vec<output::Record> = issues
+            .iter()
+            .map(|issue| {
+                vec![
+                    ("path", output::Field::Text(issue.path.display().to_string())),
+                    ("line", issue.line.map_or(output::Field::Null, output::Field::count)),
+                    ("description", output::Field::text(&issue.description)),
+                    ("repairable", output::Field::Bool(issue.repairable)),
+                    ("repaired", output::Field::Bool(issue.repairable && repair)),
+                ]
+            })
+            .collect();
+        return output::print_records(&records);
+    }
+
+    if issues.is_empty() {
+        return println!("No problems found.");
+    }
+
+    for issue in issues.iter() {
+        let location = match issue.line {
+            Some(line) => format!("{}:{}", issue.path.display(), line),
+            None => issue.path.display().to_string(),
+        };
+        let note = match (issue.repairable, repair) {
+            (true, true) => " (repaired)".green(),
+            (true, false) => " (repairable)".yellow(),
+            (false, _) => "".normal(),
+        };
+        println!("{}: {}{}", location.bold(), issue.description, note);
+    }
+
+    let repairable = issues.iter().filter(|issue| issue.repairable).count();
+    println!();
+    if repair && repairable > 0 {
+        println!(
+            "{} problems found, {} repaired. The original files were kept with a .bak extension.",
+            issues.len(),
+            repairable
+        );
+    } else if repair {
+        println!("{} problems found, none could be repaired.", issues.len());
+    } else if repairable > 0 {
+        println!(
+            "{} problems found, run 'timers fsck --repair' to repair {} of them.",
+            issues.len(),
+            repairable
+        );
+    } else {
+        println!("{} problems found.", issues.len());
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{Datelike, TimeZone};
+use colored::*;
+
+use crate::output;
+use crate::util::{
+    config, format_log, is_interactive, log_target, parse_day, parse_duration, user_input, Picked,
+};
+
+pub fn gaps_command(matches: &clap::ArgMatches) {
+    let (date, gaps) = match day_gaps(matches) {
+        Some(day_gaps) => day_gaps,
+        None => return,
+    };
+
+    if !output::is_text() {
+        let records: Vec<output::Record> = gaps
+            .iter()
+            .map(|(start, end)| {
+                vec![
+                    ("start", output::Field::Time(*start)),
+                    ("end", output::Field::Time(*end)),
+                    ("duration_seconds", output::Field::seconds(*end - *start)),
+                ]
+            })
+            .collect();
+        return output::print_records(&records);
+    }
+
+    if !config().is_work_day(date.weekday()) {
+        return println!("{} is not a work day.", date.format("%a %Y-%m-%d"));
+    }
+
+    let (work_start, work_end) = config().workday_hours();
+    let hours = format!(
+        "between {} and {}",
+        work_start.format(config().time_format()),
+        work_end.format(config().time_format())
+    );
+    if gaps.is_empty() {
+        return println!("No untracked time on {} {}.", date.format("%a %Y-%m-%d"), hours);
+    }
+
+    println!("Untracked time on {} {}:", date.format("%a %Y-%m-%d"), hours);
+    let mut total = chrono::Duration::seconds(0);
+    for gap in gaps.iter() {
+        println!("  {}", format_gap(gap));
+        total += gap.1 - gap.0;
+    }
+    println!("Total: {}", timers::format_duration(total).bold());
+}
+
+pub fn fill_command(matches: &clap::ArgMatches) {
+    if !is_interactive() {
+        return output::usage_error(
+            "Cannot ask what each gap was spent on without a terminal, use 'timers add' instead.",
+        );
+    }
+
+    let (date, gaps) = match day_gaps(matches) {
+        Some(day_gaps) => day_gaps,
+        None => return,
+    };
+    if !config().is_work_day(date.weekday()) {
+        return output::note(&format!("{} is not a work day.", date.format("%a %Y-%m-%d")));
+    }
+    if gaps.is_empty() {
+        return output::note(&format!("No untracked time on {}.", date.format("%a %Y-%m-%d")));
+    }
+
+    for gap in gaps.iter() {
+        output::note(&format_gap(gap));
+        let answer = user_input("Task (@ID or name), b for a break, Enter to skip, q to quit: ");
+        match answer.trim() {
+            "" => continue,
+            "q" => break,
+            "b" => {
+                let log = timers::Log::new(gap.0, Some(gap.1));
+                let added = timers::add_break_log(log, timers::OverlapPolicy::Trim);
+                print_added(gap, added, "Error adding break");
+            }
+            raw_task => fill_gap(gap, raw_task),
+        }
+    }
+}
+
+// The gaps in the working hours of the day given with --day, yesterday by default
+fn day_gaps(matches: &clap::ArgMatches) -> Option<(chrono::NaiveDate, Vec<timers::Gap>)> {
+    let date = parse_day(matches.value_of("day").unwrap())?;
+    let min = parse_duration(matches.value_of("min").unwrap())?;
+    if !config().is_work_day(date.weekday()) {
+        return Some((date, Vec::new()));
+    }
+
+    let (work_start, work_end) = config().workday_hours();
+    let (start, end) = match (local_time(date, work_start), local_time(date, work_end)) {
+        (Some(start), Some(end)) if start < end => (start, end),
+        _ => {
+            output::usage_error(&format!(
+                "Working hours from {} to {} are not valid on {}, see 'timers config list'.",
+                work_start.format("%H:%M"),
+                work_end.format("%H:%M"),
+                date
+            ));
+            return None;
+        }
+    };
+
+    match timers::get_gaps_between(start, end) {
+        Ok(mut gaps) => {
+            gaps.retain(|(start, end)| *end - *start >= min);
+            Some((date, gaps))
+        }
+        Err(err) => {
+            output::fail_with("Error retrieving logs", &err);
+            None
+        }
+    }
+}
+
+fn local_time(
+    date: chrono::NaiveDate,
+    time: chrono::NaiveTime,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::Local
+        .from_local_datetime(&date.and_time(time))
+        .single()
+        .map(|datetime| datetime.with_timezone(&chrono::Utc))
+}
+
+fn is_logged((start, end): &timers::Gap) -> bool {
+    timers::get_gaps_between(*start, *end).is_ok_and(|gaps| gaps.is_empty())
+}
+
+fn format_gap((start, end): &timers::Gap) -> String {
+    let log = timers::Log::new(*start, Some(*end));
+    format!("{} [{}]", format_log(&log), timers::format_duration(log.duration()))
+}
+
+// Only the parts of the gap still untracked are added, in case
+// the gap was filled by another command meanwhile
+fn fill_gap(gap: &timers::Gap, raw_task: &str) {
+    let log = timers::Log::new(gap.0, Some(gap.1));
+    let added = match log_target(raw_task, false) {
+        Some(Picked::Task(task_id)) => timers::add_log(task_id, log, timers::OverlapPolicy::Trim),
+        Some(Picked::New) => {
+            timers::create_task_with_log(raw_task, log, timers::OverlapPolicy::Trim)
+        }
+        None => return,
+    };
+
+    print_added(gap, added, "Error adding log");
+}
+
+// Adding fails when no part of the gap is left, which is
+// not an error when another command logged it meanwhile
+fn print_added(
+    gap: &timers::Gap,
+    added: Result<(timers::Task, Vec<timers::Log>), timers::Error>,
+    context: &str,
+) {
+    let (task, added) = match added {
+        Ok(added) => added,
+        Err(timers::Error::Value(_)) if is_logged(gap) => {
+            return output::note("Nothing added, the gap was logged meanwhile")
+        }
+        Err(err) => return output::fail_with(context, &err),
+    };
+
+    for log in added.iter() {
+        output::note(&format!(
+            "Added {} [{}] to @{}: {}",
+            format_log(log),
+            timers::format_duration(log.duration()),
+            task.id,
+            task.name
+        ));
+    }
+}
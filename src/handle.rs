@@ -0,0 +1,964 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::ops::Add;
+use std::path::{Path, PathBuf};
+
+use crate::clock::{Clock, SystemClock};
+use crate::errors::{Error, ValueError};
+use crate::filter::TaskFilter;
+use crate::import::{self, ImportPolicy, ImportSummary, ImportedLog};
+use crate::project::Project;
+use crate::repo::{Log, Repo, Task};
+use crate::search::{match_tasks, TaskMatch};
+use crate::storage::Storage;
+use crate::validate::check_task;
+
+/// What to do when a log being added overlaps existing logs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OverlapPolicy {
+    Reject,
+    /// Only add the parts of the log which do not overlap.
+    Trim,
+}
+
+/// Time logged in a period by the tasks matching a filter.
+#[derive(Debug, Clone)]
+pub struct PeriodTotal {
+    pub start: chrono::DateTime<chrono::Utc>,
+    pub end: chrono::DateTime<chrono::Utc>,
+    pub duration: chrono::Duration,
+    /// Number of tasks with logs in the period.
+    pub tasks: usize,
+    /// Time logged on breaks, which is not part of `duration`.
+    pub breaks: chrono::Duration,
+}
+
+/// What to set along with a new log when starting to log on a task.
+#[derive(Debug, Default, Clone)]
+pub struct LogOptions {
+    /// Note on the new log.
+    pub note: Option<String>,
+    /// Tags added to the task.
+    pub tags: Vec<String>,
+    /// Project of the task, kept as is if not given.
+    pub project: Option<Project>,
+}
+
+/// Untracked time, from its start to its end.
+pub type Gap = (chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>);
+
+/// Name of the break task, when created by the first break.
+pub const BREAK_TASK_NAME: &str = "Break";
+
+/// Entry point of the library, working on the given storage and taking
+/// the current time from the given clock.
+pub struct Timers {
+    storage: Box<dyn Storage>,
+    clock: Box<dyn Clock>,
+}
+
+impl Timers {
+    /// Uses the system time as clock.
+    pub fn new(storage: Box<dyn Storage>) -> Timers {
+        Timers::with_clock(storage, Box::new(SystemClock))
+    }
+
+    pub fn with_clock(storage: Box<dyn Storage>, clock: Box<dyn Clock>) -> Timers {
+        Timers { storage, clock }
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    pub fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        self.clock.now()
+    }
+
+    pub fn create_task(&self, name: &str) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        self.storage.create_task(name)
+    }
+
+    /// Starts logging on the task, stopping the task currently logging if any.
+    pub fn log_task_at(&self, id: u32, at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
+        self.log_task_at_with(id, at, &LogOptions::default())
+    }
+
+    /// Like log_task_at, also applying the options in the same change.
+    pub fn log_task_at_with(
+        &self,
+        id: u32,
+        at: chrono::DateTime<chrono::Utc>,
+        options: &LogOptions,
+    ) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        // Read the task first, so that nothing is stopped if it does not exist,
+        // and again after stopping, as it may be the one logging
+        self.storage.get_task(id)?;
+        self.check_log_start(at)?;
+        stop_logging_at(self.storage.as_ref(), at)?;
+        let mut task = self.storage.get_task(id)?;
+        start_log(self.storage.as_ref(), &mut task, at, options)?;
+        Ok(task)
+    }
+
+    pub fn log_task(&self, id: u32) -> Result<Task, Error> {
+        self.log_task_at(id, self.clock.now())
+    }
+
+    /// Creates a task and starts logging on it, stopping the task currently logging if any.
+    pub fn create_log_task_at(
+        &self,
+        name: &str,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Task, Error> {
+        self.create_log_task_at_with(name, at, &LogOptions::default())
+    }
+
+    /// Like create_log_task_at, also applying the options in the same change.
+    pub fn create_log_task_at_with(
+        &self,
+        name: &str,
+        at: chrono::DateTime<chrono::Utc>,
+        options: &LogOptions,
+    ) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        self.check_log_start(at)?;
+        stop_logging_at(self.storage.as_ref(), at)?;
+        let mut task = self.storage.create_task(name)?;
+        start_log(self.storage.as_ref(), &mut task, at, options)?;
+        Ok(task)
+    }
+
+    pub fn create_log_task(&self, name: &str) -> Result<Task, Error> {
+        self.create_log_task_at(name, self.clock.now())
+    }
+
+    // A log started at the given time runs until now, so it must not
+    // overlap closed logs. To be called with the lock held.
+    fn check_log_start(&self, at: chrono::DateTime<chrono::Utc>) -> Result<(), Error> {
+        let now = self.clock.now();
+        let log = Log::new(at, Some(now.max(at)));
+        let overlapping = self.find_overlapping_logs(&log)?;
+        match overlapping.iter().find(|(_, other)| other.end.is_some()) {
+            Some((other_task, other)) => Err(overlap_error(other_task, other, now)),
+            None => Ok(()),
+        }
+    }
+
+    // Logs of any task overlapping the given one
+    pub fn find_overlapping_logs(&self, log: &Log) -> Result<Vec<(Task, Log)>, Error> {
+        let mut overlapping = Vec::new();
+        let now = self.clock.now();
+        let tasks = tasks_between(self.storage.as_ref(), log.start, log.end_at(now), now)?;
+        for task in tasks.values() {
+            for other in task.logs.iter() {
+                if other.overlaps(log) {
+                    overlapping.push((task.clone(), other.clone()));
+                }
+            }
+        }
+        overlapping.sort_by_key(|(_, other)| other.start);
+
+        Ok(overlapping)
+    }
+
+    /// Adds a closed log to the task, returning the task and the logs actually added.
+    pub fn add_log(
+        &self,
+        id: u32,
+        log: Log,
+        policy: OverlapPolicy,
+    ) -> Result<(Task, Vec<Log>), Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+        let parts = self.added_parts(log, policy)?;
+
+        task.logs.extend(parts.iter().cloned());
+        self.storage.update_task(&mut task)?;
+
+        Ok((task, parts))
+    }
+
+    /// Creates a task with a closed log, returning the task and the logs actually added.
+    pub fn create_task_with_log(
+        &self,
+        name: &str,
+        log: Log,
+        policy: OverlapPolicy,
+    ) -> Result<(Task, Vec<Log>), Error> {
+        let _lock = self.storage.lock()?;
+        // The log is checked first, so that no task is created if it cannot be added
+        let parts = self.added_parts(log, policy)?;
+        let mut task = self.storage.create_task(name)?;
+
+        task.logs.extend(parts.iter().cloned());
+        self.storage.update_task(&mut task)?;
+
+        Ok((task, parts))
+    }
+
+    // The parts of a closed log to add, according to the overlap policy
+    fn added_parts(&self, log: Log, policy: OverlapPolicy) -> Result<Vec<Log>, Error> {
+        let log = Log { note: clean_note(log.note.as_deref()), ..log };
+
+        match log.end {
+            None => return Err(Error::Value(ValueError::new("Added logs must have an end."))),
+            Some(end) if end <= log.start => {
+                return Err(Error::Value(ValueError::new("The end of the log must be after its start.")))
+            }
+            Some(end) if end > self.clock.now() => {
+                return Err(Error::Value(ValueError::new("Cannot add logs ending in the future.")))
+            }
+            _ => {}
+        }
+
+        let overlapping = self.find_overlapping_logs(&log)?;
+        let parts = match policy {
+            OverlapPolicy::Trim => {
+                let others: Vec<Log> = overlapping.iter().map(|(_, other)| other.clone()).collect();
+                log.subtract(&others)
+            }
+            OverlapPolicy::Reject if !overlapping.is_empty() => {
+                let (other_task, other) = &overlapping[0];
+                return Err(overlap_error(other_task, other, self.clock.now()));
+            }
+            OverlapPolicy::Reject => vec![log],
+        };
+
+        if parts.is_empty() {
+            return Err(Error::Value(ValueError::new(
+                "The log is completely covered by existing logs.",
+            )));
+        }
+
+        Ok(parts)
+    }
+
+    pub fn get_task(&self, id: u32) -> Result<Task, Error> {
+        self.storage.get_task(id)
+    }
+
+    /// Replaces the log at the given 1-based index.
+    pub fn edit_log(&self, id: u32, index: usize, log: Log) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+
+        let i = log_index(&task, index)?;
+        task.logs[i] = Log { note: clean_note(log.note.as_deref()), ..log };
+        save_checked(self.storage.as_ref(), vec![&mut task])?;
+
+        Ok(task)
+    }
+
+    /// Sets or, with `None`, removes the note of the log at the given 1-based index.
+    pub fn set_log_note(&self, id: u32, index: usize, note: Option<&str>) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+
+        let i = log_index(&task, index)?;
+        task.logs[i].note = clean_note(note);
+        self.storage.update_task(&mut task)?;
+
+        Ok(task)
+    }
+
+    /// Sets the note of the log being logged.
+    pub fn note_current_log(&self, note: &str) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = match self.get_current_log_task()? {
+            Some(task) => task,
+            None => return Err(Error::Value(ValueError::new("No task is being logged."))),
+        };
+
+        if let Some(log) = task.logs.last_mut() {
+            log.note = clean_note(Some(note));
+        }
+        self.storage.update_task(&mut task)?;
+
+        Ok(task)
+    }
+
+    pub fn delete_log(&self, id: u32, index: usize) -> Result<(Task, Log), Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+
+        let i = log_index(&task, index)?;
+        let log = task.logs.remove(i);
+        self.storage.update_task(&mut task)?;
+
+        Ok((task, log))
+    }
+
+    /// Moves the log at the given 1-based index to another task.
+    pub fn move_log(&self, id: u32, index: usize, target_id: u32) -> Result<(Task, Task), Error> {
+        if id == target_id {
+            return Err(Error::Value(ValueError::new(
+                "The log already belongs to the task.",
+            )));
+        }
+
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+        let mut target = self.storage.get_task(target_id)?;
+
+        let i = log_index(&task, index)?;
+        target.logs.push(task.logs.remove(i));
+        save_checked(self.storage.as_ref(), vec![&mut task, &mut target])?;
+
+        Ok((task, target))
+    }
+
+    /// Splits the log at the given 1-based index in two logs at the given time.
+    pub fn split_log(
+        &self,
+        id: u32,
+        index: usize,
+        at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+
+        let i = log_index(&task, index)?;
+        let log = task.logs[i].clone();
+        if at <= log.start || at >= log.end_at(self.clock.now()) {
+            return Err(Error::Value(ValueError::new(
+                "The split time must be inside the log.",
+            )));
+        }
+
+        task.logs[i].end = Some(at);
+        task.logs.insert(i + 1, Log { start: at, ..log });
+        save_checked(self.storage.as_ref(), vec![&mut task])?;
+
+        Ok(task)
+    }
+
+    pub fn tag_task(&self, id: u32, tags: &[String]) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+        task.add_tags(tags);
+        self.storage.update_task(&mut task)?;
+        Ok(task)
+    }
+
+    pub fn set_task_project(&self, id: u32, project: Option<Project>) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+        task.project = project;
+        self.storage.update_task(&mut task)?;
+        Ok(task)
+    }
+
+    pub fn rename_task(&self, id: u32, name: &str) -> Result<Task, Error> {
+        let name = name.trim();
+        if name.is_empty() || name.contains('\n') {
+            return Err(Error::Value(ValueError::new(
+                "Task names cannot be empty or span several lines.",
+            )));
+        }
+
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+        task.name = name.to_string();
+        self.storage.update_task(&mut task)?;
+        Ok(task)
+    }
+
+    pub fn set_task_archived(&self, id: u32, archived: bool) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let mut task = self.storage.get_task(id)?;
+        task.archived = archived;
+        self.storage.update_task(&mut task)?;
+        Ok(task)
+    }
+
+    /// Deletes the task, after keeping it in the plain text format in the
+    /// `trash` directory. Returns the task, unless it could not be read.
+    pub fn delete_task(&self, id: u32, trash: &Path) -> Result<Option<Task>, Error> {
+        let _lock = self.storage.lock()?;
+        let content = self.storage.task_content(id)?;
+
+        std::fs::create_dir_all(trash)?;
+        std::fs::write(trash.join(id.to_string()), &content)?;
+        self.storage.delete_task(id)?;
+
+        Ok(Repo::parse_task_lenient(PathBuf::from(format!("@{}", id)), &content)
+            .ok()
+            .map(|(task, _)| task))
+    }
+
+    /// Brings a deleted task back from the `trash` directory, with the same ID
+    /// as it is never reused. Fails if its logs now overlap other logs.
+    pub fn restore_task(&self, id: u32, trash: &Path) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+        let path = trash.join(id.to_string());
+        if !path.exists() {
+            return Err(Error::Value(ValueError::new(
+                format!("Task @{} is not in the trash.", id).as_str(),
+            )));
+        }
+
+        let task = Repo::parse_task(PathBuf::from(format!("@{}", id)), &std::fs::read_to_string(&path)?)?;
+        check_task(&task, &self.storage.list_tasks()?)?;
+        self.storage.create_task_with_id(id, &task.name)?;
+        self.storage.replace_task(&task)?;
+        std::fs::remove_file(path)?;
+
+        self.storage.get_task(id)
+    }
+
+    /// The tasks in the `trash` directory, by ID.
+    pub fn trashed_tasks(&self, trash: &Path) -> Result<Vec<Task>, Error> {
+        let mut tasks = Vec::new();
+        if !trash.exists() {
+            return Ok(tasks);
+        }
+
+        for entry in std::fs::read_dir(trash)? {
+            let path = entry?.path();
+            if let Ok((task, _)) = Repo::parse_task_lenient(path.clone(), &std::fs::read_to_string(&path)?) {
+                tasks.push(task);
+            }
+        }
+        tasks.sort_by_key(|task| task.id);
+
+        Ok(tasks)
+    }
+
+    /// The tasks named like `name`, see `search::match_tasks`.
+    pub fn find_tasks(&self, name: &str) -> Result<Vec<TaskMatch>, Error> {
+        let mut summaries = self.storage.list_summaries()?;
+        summaries.retain(|summary| !summary.is_break);
+        Ok(match_tasks(name, summaries))
+    }
+
+    pub fn get_current_log_task(&self) -> Result<Option<Task>, Error> {
+        let summaries = self.storage.list_summaries()?;
+
+        match summaries.iter().find(|summary| summary.logging) {
+            Some(summary) => Ok(Some(self.storage.get_task(summary.id)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn stop_current_task_at(&self, at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
+        self.stop_current_task_at_with_note(at, None)
+    }
+
+    /// Stops the current task, setting the note of the log being stopped if given.
+    pub fn stop_current_task_at_with_note(
+        &self,
+        at: chrono::DateTime<chrono::Utc>,
+        note: Option<&str>,
+    ) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+
+        match self.get_current_log_task()? {
+            Some(mut task) => {
+                check_stop(&task, at)?;
+                if let (Some(note), Some(log)) = (note, task.logs.last_mut()) {
+                    log.note = clean_note(Some(note));
+                }
+                self.storage.stop_task(&mut task, at)?;
+                Ok(task)
+            }
+            None => Err(Error::Value(ValueError::new(
+                "Not task currently being logged.",
+            ))),
+        }
+    }
+
+    pub fn stop_current_task(&self) -> Result<Task, Error> {
+        self.stop_current_task_at(self.clock.now())
+    }
+
+    /// The task on which breaks are logged, if a break was ever taken.
+    pub fn get_break_task(&self) -> Result<Option<Task>, Error> {
+        match self.storage.list_summaries()?.iter().find(|summary| summary.is_break) {
+            Some(summary) => Ok(Some(self.storage.get_task(summary.id)?)),
+            None => Ok(None),
+        }
+    }
+
+    // To be called with the lock held
+    fn get_or_create_break_task(&self) -> Result<Task, Error> {
+        if let Some(break_task) = self.get_break_task()? {
+            return Ok(break_task);
+        }
+
+        let mut break_task = self.storage.create_task(BREAK_TASK_NAME)?;
+        break_task.is_break = true;
+        self.storage.replace_task(&break_task)?;
+        Ok(break_task)
+    }
+
+    /// Adds a closed log to the break task, as for `add_log`.
+    pub fn add_break_log(&self, log: Log, policy: OverlapPolicy) -> Result<(Task, Vec<Log>), Error> {
+        let id = {
+            let _lock = self.storage.lock()?;
+            self.get_or_create_break_task()?.id
+        };
+        self.add_log(id, log, policy)
+    }
+
+    /// Stops the current task and starts a break on the break task, which is
+    /// created by the first break. Returns the paused task and the break task.
+    pub fn pause_at(&self, at: chrono::DateTime<chrono::Utc>) -> Result<(Task, Task), Error> {
+        self.pause_at_with_note(at, None)
+    }
+
+    /// Like pause_at, with the given note on the break.
+    pub fn pause_at_with_note(
+        &self,
+        at: chrono::DateTime<chrono::Utc>,
+        note: Option<&str>,
+    ) -> Result<(Task, Task), Error> {
+        let _lock = self.storage.lock()?;
+
+        let paused = match self.get_current_log_task()? {
+            Some(task) if task.is_break => {
+                return Err(Error::Value(ValueError::new("Already on a break.")))
+            }
+            Some(task) => task,
+            None => return Err(Error::Value(ValueError::new("No task is being logged."))),
+        };
+        self.check_log_start(at)?;
+        stop_logging_at(self.storage.as_ref(), at)?;
+
+        let mut break_task = self.get_or_create_break_task()?;
+        let options = LogOptions {
+            note: note.map(String::from),
+            ..LogOptions::default()
+        };
+        start_log(self.storage.as_ref(), &mut break_task, at, &options)?;
+
+        Ok((self.storage.get_task(paused.id)?, break_task))
+    }
+
+    /// Ends the current break, if any, and logs again on the last task
+    /// stopped before. Without a break, resumes the last task stopped.
+    pub fn resume_at(&self, at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
+        let _lock = self.storage.lock()?;
+
+        let summaries = self.storage.list_summaries()?;
+        let working = summaries.iter().find(|summary| summary.logging && !summary.is_break);
+        if let Some(current) = working {
+            return Err(Error::Value(ValueError::new(
+                format!("Task @{} is being logged, there is nothing to resume.", current.id)
+                    .as_str(),
+            )));
+        }
+
+        let last = summaries
+            .iter()
+            .filter(|summary| !summary.is_break && summary.last_end.is_some_and(|end| end <= at))
+            .max_by_key(|summary| (summary.last_end, summary.id));
+        let id = match last {
+            Some(summary) => summary.id,
+            None => return Err(Error::Value(ValueError::new("There is no task to resume."))),
+        };
+
+        self.check_log_start(at)?;
+        stop_logging_at(self.storage.as_ref(), at)?;
+        let mut task = self.storage.get_task(id)?;
+        self.storage.log_task(&mut task, at)?;
+
+        Ok(task)
+    }
+
+    pub fn get_all_tasks(&self) -> Result<HashMap<u32, Task>, Error> {
+        self.storage.list_tasks()
+    }
+
+    pub fn get_all_tasks_between(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+    ) -> Result<HashMap<u32, Task>, Error> {
+        tasks_between(self.storage.as_ref(), start, end, self.clock.now())
+    }
+
+    /// Totals of several periods, as shown by the reports, reading
+    /// the tasks only once instead of once per period.
+    pub fn get_period_totals(
+        &self,
+        periods: &[(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)],
+        filter: &TaskFilter,
+    ) -> Result<Vec<PeriodTotal>, Error> {
+        let first_start = periods.iter().map(|(start, _)| *start).min();
+        let last_end = periods.iter().map(|(_, end)| *end).max();
+        let now = self.clock.now();
+        // Breaks are counted whatever the filter, as they are not part of any work
+        let tasks = match (first_start, last_end) {
+            (Some(start), Some(end)) => tasks_between(self.storage.as_ref(), start, end, now)?
+                .into_iter()
+                .filter(|(_, task)| task.is_break || filter.matches(task))
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        let totals = periods
+            .iter()
+            .map(|(start, end)| {
+                let mut total = PeriodTotal {
+                    start: *start,
+                    end: *end,
+                    duration: chrono::Duration::seconds(0),
+                    tasks: 0,
+                    breaks: chrono::Duration::seconds(0),
+                };
+                let logged = tasks.values().filter(|task| has_logs_between(task, *start, *end, now));
+                for task in logged {
+                    let duration = task.duration_between_at(*start, *end, now);
+                    if task.is_break {
+                        total.breaks = total.breaks.add(duration);
+                    } else {
+                        total.duration = total.duration.add(duration);
+                        total.tasks += 1;
+                    }
+                }
+                total
+            })
+            .collect();
+
+        Ok(totals)
+    }
+
+    // Returns all logs between start and end,
+    // sorted chronologically
+    pub fn get_all_logs_between(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<(Task, Log)>, Error> {
+        let tasks = self.get_all_tasks_between(start, end)?;
+        let now = self.clock.now();
+
+        let mut logs = BTreeMap::new();
+        for task in tasks.values() {
+            for log in task.logs.iter() {
+                if log.start > end || log.end_at(now) < start {
+                    continue
+                }
+
+                logs.insert((log.start, task.id), (task.clone(), log.clone()));
+            }
+        }
+
+        Ok(logs.values().cloned().collect())
+    }
+
+    /// The times between start and end, up to now, which are not covered
+    /// by any log. Breaks are logs too, so they are not gaps.
+    pub fn get_gaps_between(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<Gap>, Error> {
+        let now = self.clock.now();
+        let end = end.min(now);
+        if end <= start {
+            return Ok(Vec::new());
+        }
+
+        let mut gaps = Vec::new();
+        let mut covered = start;
+        for (_, log) in self.get_all_logs_between(start, end)? {
+            if log.start > covered {
+                gaps.push((covered, log.start.min(end)));
+            }
+            covered = covered.max(log.end_at(now));
+        }
+        if covered < end {
+            gaps.push((covered, end));
+        }
+        gaps.retain(|(gap_start, gap_end)| gap_start < gap_end);
+
+        Ok(gaps)
+    }
+
+    pub fn import_logs(
+        &self,
+        logs: Vec<ImportedLog>,
+        policy: ImportPolicy,
+    ) -> Result<ImportSummary, Error> {
+        let _lock = self.storage.lock()?;
+        import::import_logs(self.storage.as_ref(), logs, policy)
+    }
+
+    pub fn get_total_duration(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+    ) -> Result<chrono::Duration, Error> {
+        let mut total_duration = chrono::Duration::seconds(0);
+        let now = self.clock.now();
+
+        for task in tasks_between(self.storage.as_ref(), start, end, now)?.values() {
+            total_duration = total_duration.add(task.duration_between_at(start, end, now));
+        }
+
+        Ok(total_duration)
+    }
+
+    /// Replaces a task with the content of an edited task file, if it is valid
+    /// and does not overlap other tasks. Returns the task before the change, as far
+    /// as it could be read, and after the change.
+    pub fn replace_task(&self, id: u32, content: &str) -> Result<(Option<Task>, Task), Error> {
+        let _lock = self.storage.lock()?;
+
+        // Tasks are referred to by ID in the errors, whatever the storage
+        let location = PathBuf::from(format!("@{}", id));
+        let old_task = Repo::parse_task_lenient(location.clone(), &self.storage.task_content(id)?)
+            .ok()
+            .map(|(task, _)| task);
+
+        let task = Repo::parse_task(location, content)?;
+        if task.id != id {
+            return Err(Error::Value(ValueError::new(
+                format!("The task ID cannot be changed from @{} to @{}.", id, task.id).as_str(),
+            )));
+        }
+
+        let mut tasks = self.storage.list_tasks()?;
+        tasks.remove(&id);
+        check_task(&task, &tasks)?;
+        self.storage.replace_task(&task)?;
+
+        Ok((old_task, self.storage.get_task(id)?))
+    }
+
+    /// The task in the plain text format, as edited with `timers edit`.
+    pub fn task_content(&self, id: u32) -> Result<String, Error> {
+        self.storage.task_content(id)
+    }
+}
+
+// Only one task can be logging, so the current one is stopped when another
+// one starts. Done with the lock held, so that no other task starts meanwhile.
+fn stop_logging_at(storage: &dyn Storage, at: chrono::DateTime<chrono::Utc>) -> Result<(), Error> {
+    let mut logging = Vec::new();
+    for summary in storage.list_summaries()?.iter().filter(|summary| summary.logging) {
+        let task = storage.get_task(summary.id)?;
+        check_stop(&task, at)?;
+        logging.push(task);
+    }
+
+    for mut task in logging {
+        storage.stop_task(&mut task, at)?;
+    }
+
+    Ok(())
+}
+
+// A single write for the log and the options
+fn start_log(
+    storage: &dyn Storage,
+    task: &mut Task,
+    at: chrono::DateTime<chrono::Utc>,
+    options: &LogOptions,
+) -> Result<(), Error> {
+    task.add_tags(&options.tags);
+    if let Some(project) = &options.project {
+        task.project = Some(project.clone());
+    }
+    task.logs.push(Log { note: clean_note(options.note.as_deref()), ..Log::new(at, None) });
+    storage.update_task(task)
+}
+
+// The current log may also have been started while waiting for the lock
+fn check_stop(task: &Task, at: chrono::DateTime<chrono::Utc>) -> Result<(), Error> {
+    match task.logs.last() {
+        Some(log) if log.end.is_none() && at < log.start => Err(Error::Value(ValueError::new(
+            format!(
+                "The current log of @{} started at {}, after the given time.",
+                task.id,
+                log.start.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            )
+            .as_str(),
+        ))),
+        _ => Ok(()),
+    }
+}
+
+fn overlap_error(other_task: &Task, other: &Log, now: chrono::DateTime<chrono::Utc>) -> Error {
+    Error::Value(ValueError::new(
+        format!(
+            "The log overlaps a log of @{} from {} to {}.",
+            other_task.id,
+            other.start.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            other.end_at(now).with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+        )
+        .as_str(),
+    ))
+}
+
+// Only the tasks whose summary says they may have logs in the range are read
+fn tasks_between(
+    storage: &dyn Storage,
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<HashMap<u32, Task>, Error> {
+    let mut tasks = HashMap::new();
+    for summary in storage.list_summaries()? {
+        if !summary.may_overlap(start, end) {
+            continue;
+        }
+
+        let task = storage.get_task(summary.id)?;
+        if has_logs_between(&task, start, end, now) {
+            tasks.insert(task.id, task);
+        }
+    }
+
+    Ok(tasks)
+}
+
+fn has_logs_between(
+    task: &Task,
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> bool {
+    task.logs.iter().any(|log| log.start.le(&end) && log.end_at(now).ge(&start))
+}
+
+// Notes are kept on a single line, an empty note is no note
+fn clean_note(note: Option<&str>) -> Option<String> {
+    note.map(|note| note.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|note| !note.is_empty())
+}
+
+fn log_index(task: &Task, index: usize) -> Result<usize, Error> {
+    if index == 0 || index > task.logs.len() {
+        return Err(Error::Value(ValueError::new(
+            format!("Task @{} has no log #{}.", task.id, index).as_str(),
+        )));
+    }
+
+    Ok(index - 1)
+}
+
+// Validates the changed tasks against each other and against all
+// other tasks before writing any of them
+fn save_checked(storage: &dyn Storage, changed: Vec<&mut Task>) -> Result<(), Error> {
+    let mut tasks = storage.list_tasks()?;
+    for task in changed.iter() {
+        tasks.insert(task.id, (*task).clone());
+    }
+
+    for task in changed.iter() {
+        let mut sorted = (*task).clone();
+        sorted.logs.sort_by_key(|log| log.start);
+        check_task(&sorted, &tasks)?;
+    }
+
+    for task in changed {
+        storage.update_task(task)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::memory::MemoryStorage;
+
+    fn at(hour: u32, minute: u32) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc.with_ymd_and_hms(2024, 3, 4, hour, minute, 0).unwrap()
+    }
+
+    fn timers_at(now: chrono::DateTime<chrono::Utc>) -> Timers {
+        Timers::with_clock(Box::new(MemoryStorage::new()), Box::new(FixedClock(now)))
+    }
+
+    #[test]
+    fn open_logs_end_at_the_clock_time() {
+        let timers = timers_at(at(10, 0));
+        let task = timers.create_log_task_at("Write", at(9, 0)).unwrap();
+
+        let hour = chrono::Duration::hours(1);
+        let window = (at(8, 0), at(12, 0));
+
+        let totals = timers.get_period_totals(&[window], &TaskFilter::default()).unwrap();
+        assert_eq!(totals[0].duration, hour);
+        assert_eq!(timers.get_total_duration(window.0, window.1).unwrap(), hour);
+        let gaps = timers.get_gaps_between(window.0, window.1).unwrap();
+        assert_eq!(gaps, vec![(at(8, 0), at(9, 0))]);
+        assert_eq!(task.logs[0].duration_at(timers.now()), hour);
+    }
+
+    #[test]
+    fn log_options_are_applied_with_the_log() {
+        let timers = timers_at(at(12, 0));
+        let options = LogOptions {
+            note: Some("Draft".to_string()),
+            tags: vec!["docs".to_string()],
+            project: Some(Project::parse("Site").unwrap()),
+        };
+        let task = timers.create_log_task_at_with("Write", at(9, 0), &options).unwrap();
+
+        let stored = timers.get_task(task.id).unwrap();
+        assert!(stored.has_tag("docs"));
+        assert_eq!(stored.project, options.project);
+        assert_eq!(stored.logs[0].note.as_deref(), Some("Draft"));
+    }
+
+    #[test]
+    fn logs_cannot_start_inside_other_logs() {
+        let timers = timers_at(at(12, 0));
+        let first = timers.create_log_task_at("Write", at(9, 0)).unwrap();
+        timers.stop_current_task_at(at(10, 0)).unwrap();
+
+        assert!(timers.create_log_task_at("Read", at(9, 30)).is_err());
+        assert!(timers.log_task_at(first.id, at(8, 0)).is_err());
+
+        timers.log_task_at(first.id, at(11, 0)).unwrap();
+        assert!(timers.stop_current_task_at(at(10, 30)).is_err());
+        let stopped = timers.stop_current_task_at_with_note(at(11, 30), Some("Done")).unwrap();
+        assert_eq!(stopped.duration_at(timers.now()), chrono::Duration::minutes(90));
+        assert_eq!(stopped.logs[1].note.as_deref(), Some("Done"));
+    }
+
+    #[test]
+    fn tasks_logged_across_the_whole_window_are_included() {
+        let timers = timers_at(at(12, 0));
+        let log = Log::new(at(8, 0), Some(at(11, 0)));
+        let (task, _) = timers.create_task_with_log("Write", log, OverlapPolicy::Reject).unwrap();
+
+        let tasks = timers.get_all_tasks_between(at(9, 0), at(10, 0)).unwrap();
+        assert!(tasks.contains_key(&task.id));
+    }
+
+    #[test]
+    fn gaps_logged_meanwhile_are_not_filled() {
+        let timers = timers_at(at(12, 0));
+        let gap = timers.get_gaps_between(at(9, 0), at(10, 0)).unwrap()[0];
+        let log = Log::new(gap.0, Some(gap.1));
+        timers.create_task_with_log("Write", log.clone(), OverlapPolicy::Reject).unwrap();
+
+        assert!(timers.create_task_with_log("Read", log, OverlapPolicy::Trim).is_err());
+        assert!(timers.get_gaps_between(gap.0, gap.1).unwrap().is_empty());
+        assert_eq!(timers.get_all_tasks().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn logs_sharing_their_start_are_all_listed() {
+        let timers = timers_at(at(12, 0));
+        for name in ["Write", "Read"].iter() {
+            let mut task = timers.create_task(name).unwrap();
+            task.logs.push(Log::new(at(9, 0), Some(at(10, 0))));
+            timers.storage.update_task(&mut task).unwrap();
+        }
+
+        let logs = timers.get_all_logs_between(at(8, 0), at(12, 0)).unwrap();
+        assert_eq!(logs.len(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::errors::Error;
+use crate::project::Project;
+use crate::repo::{Log, Task};
+use crate::storage::Storage;
+
+/// What to do with an imported task whose ID is already taken
+/// by an existing task with a different name.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImportPolicy {
+    /// Leave the existing task alone and drop the imported one.
+    Skip,
+    /// Add the imported logs to the existing task, joining
+    /// logs that overlap logs of the same task.
+    Merge,
+    /// Import the task under the next free ID.
+    Renumber,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedLog {
+    pub task_id: u32,
+    pub task_name: String,
+    pub task_project: Option<Project>,
+    pub task_tags: Vec<String>,
+    pub log: Log,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported_logs: usize,
+    pub merged_logs: usize,
+    pub created_tasks: usize,
+    pub skipped_tasks: usize,
+    pub duplicate_logs: usize,
+    pub overlapping_logs: usize,
+}
+
+pub fn import_logs(
+    storage: &dyn Storage,
+    logs: Vec<ImportedLog>,
+    policy: ImportPolicy,
+) -> Result<ImportSummary, Error> {
+    let mut tasks = storage.list_tasks()?;
+    let mut summary = ImportSummary::default();
+
+    // Group by task, the first name found for an ID wins
+    let mut grouped: BTreeMap<u32, Task> = BTreeMap::new();
+    for imported in logs {
+        let entry = grouped
+            .entry(imported.task_id)
+            .or_insert_with(|| new_task(imported.task_id, &imported.task_name));
+        if entry.project.is_none() {
+            entry.project = imported.task_project;
+        }
+        entry.add_tags(&imported.task_tags);
+        entry.logs.push(imported.log);
+    }
+
+    for (id, imported) in grouped {
+        let mut task = match tasks.get(&id) {
+            Some(existing) if existing.name == imported.name || policy == ImportPolicy::Merge => {
+                existing.clone()
+            }
+            Some(_) if policy == ImportPolicy::Skip => {
+                summary.skipped_tasks += 1;
+                continue;
+            }
+            Some(_) => new_task(next_free_id(storage, &tasks)?, &imported.name),
+            None => new_task(id, &imported.name),
+        };
+
+        let mut logs = imported.logs;
+        logs.sort_by_key(|log| log.start);
+
+        let mut changed = false;
+        for log in logs {
+            let is_duplicate = task
+                .logs
+                .iter()
+                .any(|existing| existing.start == log.start && existing.end == log.end);
+            if is_duplicate {
+                summary.duplicate_logs += 1;
+                continue;
+            }
+
+            let overlaps_other_task = tasks
+                .values()
+                .filter(|other| other.id != task.id)
+                .any(|other| other.logs.iter().any(|existing| existing.overlaps(&log)));
+            let overlapping: Vec<usize> = task
+                .logs
+                .iter()
+                .enumerate()
+                .filter(|(_, existing)| existing.overlaps(&log))
+                .map(|(i, _)| i)
+                .collect();
+
+            if overlaps_other_task || (!overlapping.is_empty() && policy != ImportPolicy::Merge) {
+                summary.overlapping_logs += 1;
+                continue;
+            }
+
+            if overlapping.is_empty() {
+                task.logs.push(log);
+                summary.imported_logs += 1;
+            } else {
+                let mut merged = log;
+                for i in overlapping.iter().rev() {
+                    merged = join_logs(&merged, &task.logs.remove(*i));
+                }
+                task.logs.push(merged);
+                summary.merged_logs += 1;
+            }
+            changed = true;
+        }
+
+        // Existing tasks are also updated when only their project or tags changed
+        let tag_count = task.tags.len();
+        task.add_tags(&imported.tags);
+        if task.project.is_none() && imported.project.is_some() {
+            task.project = imported.project;
+            changed = changed || tasks.contains_key(&task.id);
+        }
+        if !changed && (task.tags.len() == tag_count || !tasks.contains_key(&task.id)) {
+            continue;
+        }
+
+        if !tasks.contains_key(&task.id) {
+            summary.created_tasks += 1;
+        }
+
+        storage.update_task(&mut task)?;
+        tasks.insert(task.id, task);
+    }
+
+    Ok(summary)
+}
+
+fn new_task(id: u32, name: &str) -> Task {
+    Task {
+        id,
+        path: std::path::PathBuf::new(),
+        name: name.to_string(),
+        project: None,
+        tags: Vec::new(),
+        archived: false,
+        is_break: false,
+        logs: Vec::new(),
+        logging: false,
+    }
+}
+
+// The storage knows the IDs of deleted tasks, which are not reused
+fn next_free_id(storage: &dyn Storage, tasks: &HashMap<u32, Task>) -> Result<u32, Error> {
+    let max_id = tasks.keys().max().map_or(1, |id| id + 1);
+    Ok(max_id.max(storage.next_id()?))
+}
+
+fn join_logs(a: &Log, b: &Log) -> Log {
+    Log {
+        start: a.start.min(b.start),
+        end: match (a.end, b.end) {
+            (Some(a_end), Some(b_end)) => Some(a_end.max(b_end)),
+            _ => None,
+        },
+        note: match (&a.note, &b.note) {
+            (Some(a_note), Some(b_note)) if a_note != b_note => {
+                Some(format!("{}; {}", a_note, b_note))
+            }
+            (a_note, b_note) => a_note.clone().or_else(|| b_note.clone()),
+        },
+    }
+}
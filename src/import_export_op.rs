@@ -1,29 +1,69 @@
+use std::collections::BTreeMap;
+use std::ops::Add;
 use std::path::Path;
 
-use csv;
-use timers;
-use chrono;
+use timers::{ImportPolicy, ImportedLog, Log, Project, RoundingMode};
+
 use itertools::Itertools;
 use chrono::TimeZone;
-use crate::util::parse_time;
+use crate::output;
+use crate::util::{
+    day_start, format_project, parse_int, parse_time, parse_week, task_filter, week_start_date,
+};
+
+const LOGS_HEADER: [&str; 8] = [
+    "Task ID", "Task name", "Begin (UTC)", "End (UTC)", "Duration (hours)", "Tags", "Project",
+    "Note",
+];
 
 pub fn export_command(matches: &clap::ArgMatches) {
     let object = matches.value_of("OBJECT").unwrap();
 
+    let from = match matches.value_of("from") {
+        Some(from) => match parse_time(from) {
+            Some(from) => from,
+            None => return,
+        },
+        None => chrono::Utc.with_ymd_and_hms(1900, 1, 1, 0, 0, 0).unwrap(),
+    };
+    let to = match matches.value_of("to") {
+        Some(to) => match parse_time(to) {
+            Some(to) => to,
+            None => return,
+        }
+        None => chrono::Utc.with_ymd_and_hms(2100, 1, 1, 0, 0, 0).unwrap(),
+    };
+
+    let filter = match task_filter(matches) {
+        Some(filter) => filter,
+        None => return,
+    };
+
     let output: Box<dyn std::io::Write> = match matches.value_of("output") {
         Some(output_path_str) => {
             let output_path = Path::new(output_path_str);
             match std::fs::File::create(output_path) {
                 Ok(file) => Box::new(file),
                 Err(err) => {
-                    println!("Impossible to write file '{}': {}", output_path_str, err);
-                    return;
+                    let message =
+                        format!("Impossible to write file '{}': {}", output_path_str, err);
+                    return output::fail(output::EXIT_FAILURE, &message);
                 }
             }
         },
         None => Box::new(std::io::stdout()),
     };
 
+    if object == "timesheet" {
+        export_timesheet(output, matches, &filter);
+        return;
+    }
+
+    if matches.value_of("format") == Some("json") {
+        export_json(output, from, to, &filter);
+        return;
+    }
+
     let delimiter = matches.value_of("delimiter").unwrap();
 
     let mut writer = csv::WriterBuilder::new()
@@ -32,31 +72,14 @@ pub fn export_command(matches: &clap::ArgMatches) {
 
     // Header
     if object == "logs" {
-        writer.write_record(
-            &["Task ID", "Task name", "Begin (UTC)", "End (UTC)", "Duration (hours)"]
-        ).unwrap();
+        writer.write_record(LOGS_HEADER).unwrap();
     } else {
         writer.write_record(
-            &["Task ID", "Task name", "Logs", "Duration (hours)"]
+            ["Task ID", "Task name", "Logs", "Duration (hours)", "Tags", "Project"]
         ).unwrap();
     }
 
-    let from = match matches.value_of("from") {
-        Some(from) => match parse_time(from) {
-            Some(from) => from,
-            None => return,
-        },
-        None => chrono::Utc.ymd(1900, 1, 1).and_hms(0, 0, 0),
-    };
-    let to = match matches.value_of("to") {
-        Some(to) => match parse_time(to) {
-            Some(to) => to,
-            None => return,
-        }
-        None => chrono::Utc.ymd(2100, 1, 1).and_hms(0, 0, 0),
-    };
-
-    match timers::get_all_tasks_between(from, to) {
+    match timers::get_all_tasks_between(from, to).map(|tasks| filter.apply(tasks)) {
         Ok(tasks) => for id in tasks.keys().sorted() {
             let task = tasks.get(id).unwrap();
             if object == "logs" {
@@ -65,21 +88,157 @@ pub fn export_command(matches: &clap::ArgMatches) {
                 write_task(&mut writer, task);
             }
         },
-        Err(err) => println!("Error retrieving tasks: {}", err)
+        Err(err) => output::fail_with("Error retrieving tasks", &err),
     }
 
     writer.flush().unwrap();
 }
 
+// Grid of tasks × days of the week, with hours rounded as requested
+fn export_timesheet(
+    output: Box<dyn std::io::Write>,
+    matches: &clap::ArgMatches,
+    filter: &timers::TaskFilter,
+) {
+    if matches.value_of("format") == Some("json") {
+        return output::usage_error("Timesheets can only be exported as CSV.");
+    }
+
+    let week_start = match matches.value_of("week") {
+        Some(raw_week) => match parse_week(raw_week) {
+            Some(week_start) => week_start,
+            None => return,
+        },
+        None => week_start_date(chrono::Local::now().date_naive()),
+    };
+
+    let rounding = match matches.value_of("rounding").unwrap() {
+        "none" => None,
+        raw_mode => {
+            let increment = parse_int(matches.value_of("increment").unwrap()).unwrap();
+            Some(timers::Rounding::new(RoundingMode::parse(raw_mode).unwrap(), increment).unwrap())
+        }
+    };
+    let round_per_log = matches.value_of("round-per") == Some("log");
+
+    let days: Vec<(chrono::NaiveDate, chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> =
+        (0..7)
+            .map(|i| {
+                let date = week_start + chrono::Duration::days(i);
+                (date, day_start(date), day_start(date + chrono::Duration::days(1)))
+            })
+            .collect();
+
+    let logs = match timers::get_all_logs_between(days[0].1, days[6].2) {
+        Ok(logs) => logs,
+        Err(err) => return output::fail_with("Error retrieving logs", &err),
+    };
+
+    // Time on each task for each day, logs crossing midnight are split.
+    // Breaks are not work, so they are left out.
+    let mut names = BTreeMap::new();
+    let mut grid: BTreeMap<u32, [chrono::Duration; 7]> = BTreeMap::new();
+    for (task, log) in logs.iter().filter(|(task, _)| !task.is_break && filter.matches(task)) {
+        names.insert(task.id, task.name.clone());
+        let row = grid.entry(task.id).or_insert([chrono::Duration::seconds(0); 7]);
+
+        for (i, (_, start, end)) in days.iter().enumerate() {
+            let mut duration = log.duration_between(*start, *end);
+            if let (Some(rounding), true) = (rounding, round_per_log) {
+                if duration.num_seconds() > 0 {
+                    duration = rounding.round(duration);
+                }
+            }
+            row[i] = row[i].add(duration);
+        }
+    }
+
+    if let (Some(rounding), false) = (rounding, round_per_log) {
+        for row in grid.values_mut() {
+            for cell in row.iter_mut() {
+                *cell = rounding.round(*cell);
+            }
+        }
+    }
+
+    let hours = |duration: chrono::Duration| format!("{:.2}", duration.num_seconds() as f64 / 3600.);
+
+    let delimiter = matches.value_of("delimiter").unwrap();
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter.bytes().next().unwrap())
+        .from_writer(output);
+
+    let mut header = vec!["Task ID".to_string(), "Task name".to_string()];
+    for (date, _, _) in days.iter() {
+        header.push(date.format("%a %Y-%m-%d").to_string());
+    }
+    header.push("Total (hours)".to_string());
+    writer.write_record(&header).unwrap();
+
+    let mut column_totals = [chrono::Duration::seconds(0); 7];
+    for (id, row) in grid.iter() {
+        let mut record = vec![id.to_string(), names[id].clone()];
+        let mut row_total = chrono::Duration::seconds(0);
+        for (i, cell) in row.iter().enumerate() {
+            record.push(hours(*cell));
+            row_total = row_total.add(*cell);
+            column_totals[i] = column_totals[i].add(*cell);
+        }
+        record.push(hours(row_total));
+        writer.write_record(&record).unwrap();
+    }
+
+    let mut record = vec![String::new(), "Total".to_string()];
+    let mut total = chrono::Duration::seconds(0);
+    for cell in column_totals.iter() {
+        record.push(hours(*cell));
+        total = total.add(*cell);
+    }
+    record.push(hours(total));
+    writer.write_record(&record).unwrap();
+
+    writer.flush().unwrap();
+}
+
+#[cfg(feature = "serde")]
+fn export_json(
+    mut output: Box<dyn std::io::Write>,
+    from: chrono::DateTime<chrono::Utc>,
+    to: chrono::DateTime<chrono::Utc>,
+    filter: &timers::TaskFilter,
+) {
+    match timers::get_all_tasks_between(from, to).map(|tasks| filter.apply(tasks)) {
+        Ok(mut tasks) => {
+            let tasks = tasks.drain().map(|(_, task)| task).sorted_by_key(|task| task.id).collect();
+            if let Err(err) = timers::json::write_document(&mut output, tasks) {
+                output::fail_with("Error exporting tasks", &err);
+            }
+        }
+        Err(err) => output::fail_with("Error retrieving tasks", &err),
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn export_json(
+    _output: Box<dyn std::io::Write>,
+    _from: chrono::DateTime<chrono::Utc>,
+    _to: chrono::DateTime<chrono::Utc>,
+    _filter: &timers::TaskFilter,
+) {
+    output::usage_error("JSON support was not enabled at compile time.");
+}
+
 fn write_task<T>(writer: &mut csv::Writer<T>, task: &timers::Task)
     where
         T: std::io::Write,
 {
-    writer.write_record(&[
+    writer.write_record([
         task.id.to_string().as_str(),
         task.name.as_str(),
         task.logs.len().to_string().as_str(),
         (task.duration().num_seconds() as f64 / 3600.).to_string().as_str(),
+        task.tags.join(" ").as_str(),
+        format_project(task).as_str(),
     ]).unwrap();
 }
 
@@ -101,11 +260,207 @@ fn write_log<T>(writer: &mut csv::Writer<T>, task: &timers::Task, log: &timers::
         None => String::new(),
     };
 
-    writer.write_record(&[
+    writer.write_record([
         task.id.to_string().as_str(),
         task.name.as_str(),
         log.start.to_rfc3339().as_str(),
         end_str.as_str(),
         (log.duration().num_seconds() as f64 / 3600.).to_string().as_str(),
+        task.tags.join(" ").as_str(),
+        format_project(task).as_str(),
+        log.note.as_deref().unwrap_or(""),
     ]).unwrap();
-}
\ No newline at end of file
+}
+
+pub fn import_command(matches: &clap::ArgMatches) {
+    let input_path_str = matches.value_of("FILE").unwrap();
+
+    let policy = match matches.value_of("on-conflict").unwrap() {
+        "merge" => ImportPolicy::Merge,
+        "renumber" => ImportPolicy::Renumber,
+        _ => ImportPolicy::Skip,
+    };
+
+    let is_json = match matches.value_of("format") {
+        Some(format) => format == "json",
+        None => input_path_str.to_lowercase().ends_with(".json"),
+    };
+
+    let logs = if is_json {
+        read_json_logs(input_path_str)
+    } else {
+        read_csv_logs(input_path_str, matches.value_of("delimiter").unwrap())
+    };
+    let logs = match logs {
+        Some(logs) => logs,
+        None => return,
+    };
+
+    match timers::import_logs(logs, policy) {
+        Ok(summary) if !output::is_text() => output::print_record(&vec![
+            ("imported_logs", output::Field::count(summary.imported_logs)),
+            ("merged_logs", output::Field::count(summary.merged_logs)),
+            ("created_tasks", output::Field::count(summary.created_tasks)),
+            ("duplicate_logs", output::Field::count(summary.duplicate_logs)),
+            ("overlapping_logs", output::Field::count(summary.overlapping_logs)),
+            ("skipped_tasks", output::Field::count(summary.skipped_tasks)),
+        ]),
+        Ok(summary) => {
+            println!(
+                "Imported {} logs ({} merged into existing logs), created {} tasks.",
+                summary.imported_logs + summary.merged_logs,
+                summary.merged_logs,
+                summary.created_tasks,
+            );
+            if summary.duplicate_logs > 0 {
+                println!("Skipped {} logs already present.", summary.duplicate_logs);
+            }
+            if summary.overlapping_logs > 0 {
+                println!("Skipped {} logs overlapping existing logs.", summary.overlapping_logs);
+            }
+            if summary.skipped_tasks > 0 {
+                println!(
+                    "Skipped {} tasks whose ID is used by another task.",
+                    summary.skipped_tasks,
+                );
+            }
+        }
+        Err(err) => output::fail_with("Error importing logs", &err),
+    }
+}
+
+fn read_csv_logs(input_path_str: &str, delimiter: &str) -> Option<Vec<ImportedLog>> {
+    let mut reader = match csv::ReaderBuilder::new()
+        .delimiter(delimiter.bytes().next().unwrap())
+        .from_path(input_path_str)
+    {
+        Ok(reader) => reader,
+        Err(err) => {
+            let message = format!("Impossible to read file '{}': {}", input_path_str, err);
+            output::fail(output::EXIT_FAILURE, &message);
+            return None;
+        }
+    };
+
+    match reader.headers() {
+        Ok(header) if header.iter().take(4).eq(LOGS_HEADER.iter().take(4).copied()) => {}
+        Ok(_) => {
+            let message = format!(
+                "File '{}' is not a logs export, expected columns: {}",
+                input_path_str,
+                LOGS_HEADER.join(delimiter),
+            );
+            output::fail(output::EXIT_FAILURE, &message);
+            return None;
+        }
+        Err(err) => {
+            let message = format!("Impossible to read file '{}': {}", input_path_str, err);
+            output::fail(output::EXIT_FAILURE, &message);
+            return None;
+        }
+    }
+
+    let mut logs = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        // Header is line 1
+        let line = i + 2;
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                let message = format!("Error reading line {}: {}", line, err);
+                output::fail(output::EXIT_FAILURE, &message);
+                return None;
+            }
+        };
+
+        match read_log(&record) {
+            Some(imported) => logs.push(imported),
+            None => {
+                let message =
+                    format!("Invalid log on line {}: {}", line, record.iter().join(delimiter));
+                output::fail(output::EXIT_FAILURE, &message);
+                return None;
+            }
+        }
+    }
+
+    Some(logs)
+}
+
+#[cfg(feature = "serde")]
+fn read_json_logs(input_path_str: &str) -> Option<Vec<ImportedLog>> {
+    let file = match std::fs::File::open(input_path_str) {
+        Ok(file) => file,
+        Err(err) => {
+            let message = format!("Impossible to read file '{}': {}", input_path_str, err);
+            output::fail(output::EXIT_FAILURE, &message);
+            return None;
+        }
+    };
+
+    let document = match timers::json::read_document(std::io::BufReader::new(file)) {
+        Ok(document) => document,
+        Err(err) => {
+            output::fail_with(&format!("Error reading '{}'", input_path_str), &err);
+            return None;
+        }
+    };
+
+    let mut logs = Vec::new();
+    for task in document.tasks {
+        for log in task.logs {
+            logs.push(ImportedLog {
+                task_id: task.id,
+                task_name: task.name.clone(),
+                task_project: task.project.clone(),
+                task_tags: task.tags.clone(),
+                log,
+            });
+        }
+    }
+
+    Some(logs)
+}
+
+#[cfg(not(feature = "serde"))]
+fn read_json_logs(_input_path_str: &str) -> Option<Vec<ImportedLog>> {
+    output::usage_error("JSON support was not enabled at compile time.");
+    None
+}
+
+fn read_log(record: &csv::StringRecord) -> Option<ImportedLog> {
+    let task_id = record.get(0)?.trim().parse::<u32>().ok()?;
+    let task_name = record.get(1)?.trim().to_string();
+    let start = chrono::DateTime::parse_from_rfc3339(record.get(2)?.trim()).ok()?;
+    let end = match record.get(3)?.trim() {
+        "" => None,
+        raw_end => Some(chrono::DateTime::parse_from_rfc3339(raw_end).ok()?),
+    };
+
+    if task_name.is_empty() || end.is_some_and(|end| end < start) {
+        return None;
+    }
+
+    // Exports from older versions have no tags, project and note columns
+    let task_tags = match record.get(5) {
+        Some(raw_tags) => raw_tags.split_whitespace().map(|tag| tag.to_string()).collect(),
+        None => Vec::new(),
+    };
+    let task_project = match record.get(6).map(|raw_project| raw_project.trim()) {
+        Some(raw_project) if !raw_project.is_empty() => Some(Project::parse(raw_project).ok()?),
+        _ => None,
+    };
+    let note = record.get(7).map(|note| note.trim()).filter(|note| !note.is_empty());
+
+    Some(ImportedLog {
+        task_id,
+        task_name,
+        task_project,
+        task_tags,
+        log: Log {
+            start: start.with_timezone(&chrono::Utc),
+            end: end.map(|end| end.with_timezone(&chrono::Utc)),
+            note: note.map(String::from),
+        },
+    })
+}
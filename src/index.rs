@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::errors::Error;
+use crate::repo::Task;
+use crate::storage::TaskSummary;
+
+const INDEX_FILE: &str = ".index";
+const INDEX_HEADER: &str = "# timers index 2";
+
+/// Summaries of the task files of a plain text repository, so that
+/// queries do not have to parse every task. Each entry remembers the
+/// modification time and size of its file, and is only trusted while
+/// they match: the index can always be deleted or go stale safely.
+/// Files which could not be read are remembered too, until they change.
+pub struct TaskIndex {
+    path: PathBuf,
+    entries: HashMap<u32, IndexEntry>,
+    changed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct FileStamp {
+    modified: u128,
+    size: u64,
+}
+
+struct IndexEntry {
+    stamp: FileStamp,
+    /// None if the file could not be read as a task.
+    summary: Option<TaskSummary>,
+}
+
+impl TaskIndex {
+    /// Load the index of the repository at `dir`, an unreadable
+    /// index is treated as empty and rebuilt.
+    pub fn load(dir: &Path) -> TaskIndex {
+        let mut path = dir.to_path_buf();
+        path.push(INDEX_FILE);
+
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .filter(|content| content.lines().next() == Some(INDEX_HEADER))
+            .map(|content| content.lines().skip(1).filter_map(parse_entry).collect())
+            .unwrap_or_default();
+
+        TaskIndex {
+            path,
+            entries,
+            changed: false,
+        }
+    }
+
+    /// The summary of the task file, if the entry is still up to date.
+    pub fn get(&self, file_id: u32, file: &Path) -> Option<TaskSummary> {
+        self.current(file_id, file)?.summary.clone()
+    }
+
+    /// Whether the file could not be read when last indexed, and has not changed since.
+    pub fn is_unreadable(&self, file_id: u32, file: &Path) -> bool {
+        self.current(file_id, file).is_some_and(|entry| entry.summary.is_none())
+    }
+
+    fn current(&self, file_id: u32, file: &Path) -> Option<&IndexEntry> {
+        let entry = self.entries.get(&file_id)?;
+        if file_stamp(file).ok()? == entry.stamp {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Record the task as it is in its file, which has just been read or written.
+    pub fn update(&mut self, file_id: u32, file: &Path, task: &Task) {
+        self.insert(file_id, file, Some(TaskSummary::of(task)));
+    }
+
+    /// Record that the file could not be read, so that it is not read again until it changes.
+    pub fn update_unreadable(&mut self, file_id: u32, file: &Path) {
+        self.insert(file_id, file, None);
+    }
+
+    fn insert(&mut self, file_id: u32, file: &Path, summary: Option<TaskSummary>) {
+        match file_stamp(file) {
+            Ok(stamp) => {
+                self.entries.insert(file_id, IndexEntry { stamp, summary });
+            }
+            Err(_) => {
+                self.entries.remove(&file_id);
+            }
+        }
+        self.changed = true;
+    }
+
+    /// Forget the tasks whose file is not in the given list.
+    pub fn retain(&mut self, file_ids: &[u32]) {
+        let count = self.entries.len();
+        self.entries.retain(|id, _| file_ids.contains(id));
+        self.changed = self.changed || self.entries.len() != count;
+    }
+
+    /// Write the index if it changed. Failing to do so only makes
+    /// the next queries slower, so callers may ignore the error.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let mut ids: Vec<&u32> = self.entries.keys().collect();
+        ids.sort();
+
+        let mut content = format!("{}\n", INDEX_HEADER);
+        for id in ids {
+            content.push_str(&format_entry(*id, &self.entries[id]));
+            content.push('\n');
+        }
+
+        let temp_path = self.path.with_extension(format!("{}.tmp", std::process::id()));
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        std::fs::rename(&temp_path, &self.path)?;
+
+        self.changed = false;
+        Ok(())
+    }
+}
+
+fn file_stamp(file: &Path) -> Result<FileStamp, Error> {
+    let metadata = std::fs::metadata(file)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_nanos())
+        .unwrap_or(0);
+
+    Ok(FileStamp {
+        modified,
+        size: metadata.len(),
+    })
+}
+
+// One line per task file: file id, modification time, size, task id, whether
+// the task is logging, whether it is the break task, first start, last end and
+// name, separated by tabs. Unreadable files only have the first three and a !
+fn format_entry(file_id: u32, entry: &IndexEntry) -> String {
+    let format_time = |time: Option<chrono::DateTime<chrono::Utc>>| {
+        time.map_or("-".to_string(), |time| time.to_rfc3339())
+    };
+
+    let summary = match &entry.summary {
+        Some(summary) => summary,
+        None => return format!("{}\t{}\t{}\t!", file_id, entry.stamp.modified, entry.stamp.size),
+    };
+
+    format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+        file_id,
+        entry.stamp.modified,
+        entry.stamp.size,
+        summary.id,
+        if summary.logging { 1 } else { 0 },
+        if summary.is_break { 1 } else { 0 },
+        format_time(summary.first_start),
+        format_time(summary.last_end),
+        summary.name,
+    )
+}
+
+fn parse_entry(line: &str) -> Option<(u32, IndexEntry)> {
+    let parse_time = |raw: &str| -> Option<Option<chrono::DateTime<chrono::Utc>>> {
+        if raw == "-" {
+            return Some(None);
+        }
+        chrono::DateTime::parse_from_rfc3339(raw)
+            .ok()
+            .map(|time| Some(time.with_timezone(&chrono::Utc)))
+    };
+
+    let fields: Vec<&str> = line.splitn(9, '\t').collect();
+    let stamp = FileStamp {
+        modified: fields.get(1)?.parse().ok()?,
+        size: fields.get(2)?.parse().ok()?,
+    };
+    if fields.len() == 4 && fields[3] == "!" {
+        return Some((fields[0].parse().ok()?, IndexEntry { stamp, summary: None }));
+    }
+    if fields.len() != 9 {
+        return None;
+    }
+
+    let entry = IndexEntry {
+        stamp,
+        summary: Some(TaskSummary {
+            id: fields[3].parse().ok()?,
+            logging: fields[4] == "1",
+            is_break: fields[5] == "1",
+            first_start: parse_time(fields[6])?,
+            last_end: parse_time(fields[7])?,
+            name: fields[8].to_string(),
+        }),
+    };
+
+    Some((fields[0].parse().ok()?, entry))
+}
@@ -0,0 +1,486 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::errors::{Error, ValueError};
+use crate::repo::{Repo, Task};
+use crate::storage::{Storage, StorageLock, TaskSummary};
+
+const JOURNAL_FILE: &str = ".journal";
+// Last sequence number given, so that the journal is not read for each entry
+const SEQ_FILE: &str = ".journal_seq";
+const JOURNAL_HEADER: &str = "# timers journal 1";
+
+/// What an entry of the journal did: an operation changing tasks,
+/// or the undo or redo of an earlier entry.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EntryKind {
+    Do,
+    Undo(u32),
+    Redo(u32),
+    /// Tasks were changed outside the journal, by a repair or a migration,
+    /// so the earlier entries can no longer be undone or redone.
+    Reset,
+}
+
+/// A task before and after an operation, in the plain text format of the
+/// task files, `None` when the task did not exist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskChange {
+    pub id: u32,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    pub seq: u32,
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub kind: EntryKind,
+    pub description: String,
+    pub changes: Vec<TaskChange>,
+}
+
+/// Append-only record of the changes made to the tasks of a profile,
+/// kept in its `.journal` file, from which they can be undone and redone.
+pub struct Journal {
+    path: PathBuf,
+    seq_path: PathBuf,
+}
+
+impl Journal {
+    pub fn open(dir: &Path) -> Journal {
+        Journal {
+            path: dir.join(JOURNAL_FILE),
+            seq_path: dir.join(SEQ_FILE),
+        }
+    }
+
+    /// All entries, oldest first. An entry left incomplete by a crash is ignored.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, Error> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = std::fs::read_to_string(&self.path)?;
+        Ok(parse_entries(&content))
+    }
+
+    /// Appends an entry, to be called with the storage lock held so that
+    /// concurrent invocations never give the same sequence number.
+    pub fn record(
+        &self,
+        kind: EntryKind,
+        description: &str,
+        changes: Vec<TaskChange>,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Result<JournalEntry, Error> {
+        let entry = JournalEntry {
+            seq: self.next_seq()?,
+            time,
+            kind,
+            description: description.replace('\n', " "),
+            changes,
+        };
+
+        let mut content = String::new();
+        if !self.path.exists() {
+            content.push_str(JOURNAL_HEADER);
+            content.push('\n');
+        }
+        content.push_str(&format_entry(&entry));
+
+        // A single write, so that entries of concurrent invocations do not mix
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(content.as_bytes())?;
+
+        Ok(entry)
+    }
+
+    // The number is taken before the entry is written, so that a crash
+    // in between skips it instead of giving it again
+    fn next_seq(&self) -> Result<u32, Error> {
+        let last = std::fs::read_to_string(&self.seq_path)
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok());
+        // Missing in journals written before it was kept
+        let last = match last {
+            Some(last) => last,
+            None => self.entries()?.last().map_or(0, |last| last.seq),
+        };
+
+        std::fs::write(&self.seq_path, format!("{}\n", last + 1))?;
+        Ok(last + 1)
+    }
+
+    /// Reverts the last operation which was not undone yet, returning the
+    /// entry recording the undo. Fails if its tasks were changed since.
+    pub fn undo(
+        &self,
+        storage: &dyn Storage,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Result<JournalEntry, Error> {
+        let _lock = storage.lock()?;
+        let entries = self.entries()?;
+        let (done, _) = stacks(&entries);
+        let entry = match done.last() {
+            Some(seq) => find_entry(&entries, *seq),
+            None => return Err(Error::Value(ValueError::new("There is nothing to undo."))),
+        };
+
+        let changes: Vec<TaskChange> = entry
+            .changes
+            .iter()
+            .rev()
+            .map(|change| TaskChange {
+                id: change.id,
+                before: change.after.clone(),
+                after: change.before.clone(),
+            })
+            .collect();
+        apply_changes(storage, entry, &changes)?;
+
+        self.record(EntryKind::Undo(entry.seq), &entry.description, changes, time)
+    }
+
+    /// Applies again the last operation undone, unless other operations
+    /// were recorded since. Returns the entry recording the redo.
+    pub fn redo(
+        &self,
+        storage: &dyn Storage,
+        time: chrono::DateTime<chrono::Utc>,
+    ) -> Result<JournalEntry, Error> {
+        let _lock = storage.lock()?;
+        let entries = self.entries()?;
+        let (_, undone) = stacks(&entries);
+        let entry = match undone.last() {
+            Some(seq) => find_entry(&entries, *seq),
+            None => return Err(Error::Value(ValueError::new("There is nothing to redo."))),
+        };
+
+        let changes = entry.changes.clone();
+        apply_changes(storage, entry, &changes)?;
+
+        self.record(EntryKind::Redo(entry.seq), &entry.description, changes, time)
+    }
+}
+
+/// The operations which are currently undone.
+pub fn undone_entries(entries: &[JournalEntry]) -> HashSet<u32> {
+    let mut undone = HashSet::new();
+    for entry in entries {
+        match entry.kind {
+            EntryKind::Undo(seq) => undone.insert(seq),
+            EntryKind::Redo(seq) => undone.remove(&seq),
+            EntryKind::Do | EntryKind::Reset => false,
+        };
+    }
+
+    undone
+}
+
+// The operations which can be undone and the ones which can be redone,
+// the next one last. New operations cannot be redone once others are made,
+// and nothing before a reset can be undone or redone
+fn stacks(entries: &[JournalEntry]) -> (Vec<u32>, Vec<u32>) {
+    let mut done = Vec::new();
+    let mut undone = Vec::new();
+    for entry in entries {
+        match entry.kind {
+            EntryKind::Do => {
+                done.push(entry.seq);
+                undone.clear();
+            }
+            EntryKind::Undo(seq) => {
+                done.retain(|other| *other != seq);
+                undone.push(seq);
+            }
+            EntryKind::Redo(seq) => {
+                undone.retain(|other| *other != seq);
+                done.push(seq);
+            }
+            EntryKind::Reset => {
+                done.clear();
+                undone.clear();
+            }
+        }
+    }
+
+    (done, undone)
+}
+
+fn find_entry(entries: &[JournalEntry], seq: u32) -> &JournalEntry {
+    // Cannot panic, the stacks only hold sequence numbers of entries
+    entries.iter().find(|entry| entry.seq == seq).unwrap()
+}
+
+// Only done if every task is still as the entry left it,
+// not to lose the changes made since
+fn apply_changes(
+    storage: &dyn Storage,
+    entry: &JournalEntry,
+    changes: &[TaskChange],
+) -> Result<(), Error> {
+    for change in changes {
+        if task_state(storage, change.id)? != change.before {
+            return Err(Error::Value(ValueError::new(
+                format!(
+                    "Task @{} was changed after #{} ({}), these changes would be lost.",
+                    change.id, entry.seq, entry.description
+                )
+                .as_str(),
+            )));
+        }
+    }
+
+    for change in changes {
+        match &change.after {
+            Some(content) => {
+                let location = PathBuf::from(format!("@{}", change.id));
+                let (task, _) = Repo::parse_task_lenient(location, content)?;
+                if change.before.is_none() {
+                    storage.create_task_with_id(change.id, &task.name)?;
+                }
+                storage.replace_task(&task)?;
+            }
+            None => storage.delete_task(change.id)?,
+        }
+    }
+
+    Ok(())
+}
+
+// The task as it is stored, None if it does not exist
+fn task_state(storage: &dyn Storage, id: u32) -> Result<Option<String>, Error> {
+    match storage.task_content(id) {
+        Ok(content) => Ok(Some(content)),
+        Err(Error::Value(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// The changes recorded by a recording storage.
+pub type Changes = Rc<RefCell<Vec<TaskChange>>>;
+
+/// Passes everything to the given storage, recording the changes made to
+/// the tasks. Each task is recorded once, as it was before the first change
+/// and after the last one.
+///
+/// The storage stays locked as long as the recording storage exists, so that
+/// the changes can be written to the journal before anyone else changes tasks.
+pub struct RecordingStorage {
+    inner: Box<dyn Storage>,
+    changes: Changes,
+    _lock: StorageLock,
+}
+
+impl RecordingStorage {
+    /// The storage and the changes it records, to be read once done with it.
+    pub fn new(
+        inner: Box<dyn Storage>,
+    ) -> Result<(RecordingStorage, Changes), Error> {
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let storage = RecordingStorage {
+            _lock: inner.lock()?,
+            inner,
+            changes: changes.clone(),
+        };
+
+        Ok((storage, changes))
+    }
+
+    fn before_change(&self, id: u32) -> Result<(), Error> {
+        if self.changes.borrow().iter().any(|change| change.id == id) {
+            return Ok(());
+        }
+
+        let before = task_state(self.inner.as_ref(), id)?;
+        self.changes.borrow_mut().push(TaskChange {
+            id,
+            after: before.clone(),
+            before,
+        });
+        Ok(())
+    }
+
+    fn after_change(&self, id: u32) -> Result<(), Error> {
+        let after = task_state(self.inner.as_ref(), id)?;
+        if let Some(change) = self.changes.borrow_mut().iter_mut().find(|change| change.id == id) {
+            change.after = after;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for RecordingStorage {
+    fn list_tasks(&self) -> Result<HashMap<u32, Task>, Error> {
+        self.inner.list_tasks()
+    }
+
+    fn get_task(&self, id: u32) -> Result<Task, Error> {
+        self.inner.get_task(id)
+    }
+
+    fn create_task_with_id(&self, id: u32, name: &str) -> Result<Task, Error> {
+        self.before_change(id)?;
+        let task = self.inner.create_task_with_id(id, name)?;
+        self.after_change(id)?;
+        Ok(task)
+    }
+
+    fn replace_task(&self, task: &Task) -> Result<(), Error> {
+        self.before_change(task.id)?;
+        self.inner.replace_task(task)?;
+        self.after_change(task.id)
+    }
+
+    fn delete_task(&self, id: u32) -> Result<(), Error> {
+        self.before_change(id)?;
+        self.inner.delete_task(id)?;
+        self.after_change(id)
+    }
+
+    fn next_id(&self) -> Result<u32, Error> {
+        self.inner.next_id()
+    }
+
+    // Already locked, and the lock cannot be taken twice
+    fn lock(&self) -> Result<StorageLock, Error> {
+        Ok(StorageLock::none())
+    }
+
+    fn list_summaries(&self) -> Result<Vec<TaskSummary>, Error> {
+        self.inner.list_summaries()
+    }
+
+    fn task_content(&self, id: u32) -> Result<String, Error> {
+        self.inner.task_content(id)
+    }
+}
+
+// An entry starts with its sequence number, time, kind and description,
+// followed by the states of its tasks, with lines prefixed by '|'
+fn format_entry(entry: &JournalEntry) -> String {
+    let kind = match entry.kind {
+        EntryKind::Do => "do".to_string(),
+        EntryKind::Undo(seq) => format!("undo:{}", seq),
+        EntryKind::Redo(seq) => format!("redo:{}", seq),
+        EntryKind::Reset => "reset".to_string(),
+    };
+    let mut content = format!(
+        "entry {} {} {} {}\n",
+        entry.seq,
+        entry.time.to_rfc3339(),
+        kind,
+        entry.description
+    );
+
+    for change in entry.changes.iter() {
+        for (label, state) in [("before", &change.before), ("after", &change.after)] {
+            match state {
+                Some(state) => {
+                    content.push_str(&format!("{} {}\n", label, change.id));
+                    for line in state.lines() {
+                        content.push_str(&format!("|{}\n", line));
+                    }
+                }
+                None => content.push_str(&format!("{} {} -\n", label, change.id)),
+            }
+        }
+    }
+    content.push_str("end\n");
+
+    content
+}
+
+fn parse_entries(content: &str) -> Vec<JournalEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<JournalEntry> = None;
+    // The state being read, as the change it belongs to and whether it is the after state
+    let mut state: Option<(usize, bool)> = None;
+
+    for line in content.lines() {
+        if let Some(header) = line.strip_prefix("entry ") {
+            current = parse_header(header);
+            state = None;
+        } else if line == "end" {
+            entries.extend(current.take());
+        } else if let Some(entry) = current.as_mut() {
+            if let Some(text) = line.strip_prefix('|') {
+                if let Some((index, after)) = state {
+                    let change = &mut entry.changes[index];
+                    let target = if after { &mut change.after } else { &mut change.before };
+                    if let Some(target) = target.as_mut() {
+                        target.push_str(text);
+                        target.push('\n');
+                    }
+                }
+            } else {
+                state = parse_state(entry, line);
+                if state.is_none() {
+                    current = None;
+                }
+            }
+        }
+    }
+
+    entries
+}
+
+fn parse_header(header: &str) -> Option<JournalEntry> {
+    let mut fields = header.splitn(4, ' ');
+    let seq = fields.next()?.parse().ok()?;
+    let time = chrono::DateTime::parse_from_rfc3339(fields.next()?).ok()?;
+    let raw_kind = fields.next()?;
+    let kind = match raw_kind.split_once(':') {
+        None if raw_kind == "reset" => EntryKind::Reset,
+        None => EntryKind::Do,
+        Some(("undo", seq)) => EntryKind::Undo(seq.parse().ok()?),
+        Some(("redo", seq)) => EntryKind::Redo(seq.parse().ok()?),
+        Some(_) => return None,
+    };
+
+    Some(JournalEntry {
+        seq,
+        time: time.with_timezone(&chrono::Utc),
+        kind,
+        description: fields.next().unwrap_or("").to_string(),
+        changes: Vec::new(),
+    })
+}
+
+// Starts the before or after state of a task, with a new change for the before state
+fn parse_state(entry: &mut JournalEntry, line: &str) -> Option<(usize, bool)> {
+    let mut fields = line.split(' ');
+    let after = match fields.next()? {
+        "before" => false,
+        "after" => true,
+        _ => return None,
+    };
+    let id = fields.next()?.parse().ok()?;
+    let state = match fields.next() {
+        Some("-") => None,
+        None => Some(String::new()),
+        Some(_) => return None,
+    };
+
+    if !after {
+        entry.changes.push(TaskChange {
+            id,
+            before: state,
+            after: None,
+        });
+    } else {
+        match entry.changes.last_mut() {
+            Some(change) if change.id == id => change.after = state,
+            _ => return None,
+        }
+    }
+
+    Some((entry.changes.len() - 1, after))
+}
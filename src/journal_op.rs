@@ -0,0 +1,152 @@
+use colored::*;
+
+use crate::output;
+use crate::util::{config, parse_int, resolve_task};
+
+pub fn undo_command(matches: &clap::ArgMatches) {
+    repeat(matches, ("Undid", "Error undoing"), timers::undo);
+}
+
+pub fn redo_command(matches: &clap::ArgMatches) {
+    repeat(matches, ("Redid", "Error redoing"), timers::redo);
+}
+
+// Undoes or redoes the given number of entries, stopping at the first failure
+fn repeat(
+    matches: &clap::ArgMatches,
+    (done, failed): (&str, &str),
+    operation: fn() -> Result<timers::JournalEntry, timers::Error>,
+) {
+    let raw_count = matches.value_of("count").unwrap();
+    let count = match parse_int(raw_count) {
+        Ok(count) if count > 0 => count,
+        _ => return output::usage_error(&format!("Invalid number of changes: '{}'", raw_count)),
+    };
+
+    let mut records = Vec::new();
+    for _ in 0..count {
+        match operation() {
+            Ok(entry) => {
+                let target = match entry.kind {
+                    timers::EntryKind::Undo(seq) | timers::EntryKind::Redo(seq) => seq,
+                    timers::EntryKind::Do | timers::EntryKind::Reset => entry.seq,
+                };
+                output::note(&format!("{} #{}: {}", done, target, entry.description));
+                records.push(entry_record(&entry, false));
+            }
+            Err(err) => {
+                output::fail_with(failed, &err);
+                break;
+            }
+        }
+    }
+
+    if !output::is_text() {
+        output::print_records(&records);
+    }
+}
+
+pub fn history_command(matches: &clap::ArgMatches) {
+    let raw_num = matches.value_of("num").unwrap();
+    let num = match parse_int(raw_num) {
+        Ok(num) => num as usize,
+        Err(_) => return output::usage_error(&format!("Invalid number of entries: '{}'", raw_num)),
+    };
+
+    let task_id = match matches.value_of("TASK") {
+        Some(raw_task) => match resolve_task(raw_task) {
+            Some(task_id) => Some(task_id),
+            None => return,
+        },
+        None => None,
+    };
+
+    let entries = match timers::history() {
+        Ok(entries) => entries,
+        Err(err) => return output::fail_with("Error reading the history", &err),
+    };
+    let undone = timers::undone_entries(&entries);
+
+    let entries: Vec<&timers::JournalEntry> = entries
+        .iter()
+        .filter(|entry| task_id.is_none_or(|id| entry.changes.iter().any(|change| change.id == id)))
+        .collect();
+    let entries = &entries[entries.len().saturating_sub(num)..];
+
+    if !output::is_text() {
+        let records: Vec<output::Record> = entries
+            .iter()
+            .map(|entry| entry_record(entry, undone.contains(&entry.seq)))
+            .collect();
+        return output::print_records(&records);
+    }
+
+    for entry in entries {
+        let time = entry.time.with_timezone(&chrono::Local).format(config().date_format());
+        let tasks: Vec<String> =
+            entry.changes.iter().map(|change| format!("@{}", change.id)).collect();
+        let line = format!(
+            "{:<6} {:<16} {:<48} {}",
+            format!("#{}", entry.seq),
+            time.to_string(),
+            describe(entry),
+            tasks.join(" ")
+        );
+
+        if undone.contains(&entry.seq) {
+            println!("{} {}", line.dimmed(), "(undone)".dimmed());
+        } else {
+            println!("{}", line);
+        }
+
+        if matches.is_present("long") {
+            print_changes(entry);
+        }
+    }
+}
+
+fn describe(entry: &timers::JournalEntry) -> String {
+    match entry.kind {
+        timers::EntryKind::Do => entry.description.clone(),
+        timers::EntryKind::Reset => format!("{} (cannot be undone)", entry.description),
+        timers::EntryKind::Undo(seq) => format!("Undo #{}: {}", seq, entry.description),
+        timers::EntryKind::Redo(seq) => format!("Redo #{}: {}", seq, entry.description),
+    }
+}
+
+// The lines of the task files removed and added by the entry
+fn print_changes(entry: &timers::JournalEntry) {
+    for change in entry.changes.iter() {
+        let before: Vec<&str> = change.before.as_deref().unwrap_or("").lines().collect();
+        let after: Vec<&str> = change.after.as_deref().unwrap_or("").lines().collect();
+
+        for line in before.iter().filter(|line| !after.contains(line)) {
+            println!("    {} {}", "-".red(), line.red());
+        }
+        for line in after.iter().filter(|line| !before.contains(line)) {
+            println!("    {} {}", "+".green(), line.green());
+        }
+    }
+}
+
+fn entry_record(entry: &timers::JournalEntry, undone: bool) -> output::Record {
+    let (kind, target) = match entry.kind {
+        timers::EntryKind::Do => ("do", None),
+        timers::EntryKind::Reset => ("reset", None),
+        timers::EntryKind::Undo(seq) => ("undo", Some(seq)),
+        timers::EntryKind::Redo(seq) => ("redo", Some(seq)),
+    };
+
+    vec![
+        ("seq", output::Field::Int(entry.seq as i64)),
+        ("time", output::Field::Time(entry.time)),
+        ("kind", output::Field::text(kind)),
+        ("target", target.map_or(output::Field::Null, |seq| output::Field::Int(seq as i64))),
+        ("description", output::Field::text(&entry.description)),
+        (
+            "tasks",
+            output::Field::List(entry.changes.iter().map(|change| change.id.to_string()).collect()),
+        ),
+        ("undone", output::Field::Bool(undone)),
+    ]
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, ValueError};
+use crate::repo::Task;
+
+/// Version of the JSON document layout. Increase it whenever a change
+/// could break existing readers; adding new fields with defaults does not.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Document {
+    pub version: u32,
+    pub tasks: Vec<Task>,
+}
+
+impl Document {
+    pub fn new(tasks: Vec<Task>) -> Document {
+        Document {
+            version: SCHEMA_VERSION,
+            tasks,
+        }
+    }
+}
+
+pub fn write_document<W: std::io::Write>(mut writer: W, tasks: Vec<Task>) -> Result<(), Error> {
+    serde_json::to_writer_pretty(&mut writer, &Document::new(tasks))
+        .map_err(|err| Error::Value(ValueError::new(format!("Cannot write JSON: {}", err).as_str())))?;
+    writeln!(writer)?;
+
+    Ok(())
+}
+
+pub fn read_document<R: std::io::Read>(reader: R) -> Result<Document, Error> {
+    let document: Document = serde_json::from_reader(reader)
+        .map_err(|err| Error::Value(ValueError::new(format!("Invalid JSON: {}", err).as_str())))?;
+
+    if document.version > SCHEMA_VERSION {
+        return Err(Error::Value(ValueError::new(
+            format!(
+                "JSON schema version {} is newer than the supported version {}.",
+                document.version, SCHEMA_VERSION
+            )
+            .as_str(),
+        )));
+    }
+
+    Ok(document)
+}
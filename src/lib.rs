@@ -1,26 +1,50 @@
 use std::collections::HashMap;
-use std::collections::BTreeMap;
-use std::ops::Add;
 use std::path::PathBuf;
 
-use chrono;
-use dirs;
-use fs_extra;
-
 mod errors;
 pub use errors::{Error, ValueError};
 mod repo;
 pub use repo::{Log, Repo, Task, TaskStatus};
-
-fn data_path() -> PathBuf {
-    let mut path = dirs::data_dir().unwrap();
-    path.push("timers_time_logs");
-    path
-}
-
-fn get_repo() -> Result<Repo, Error> {
-    let path = data_path();
-
+mod index;
+mod storage;
+pub use storage::{Storage, StorageKind, StorageLock, TaskSummary};
+mod memory;
+pub use memory::MemoryStorage;
+mod clock;
+pub use clock::{Clock, FixedClock, SystemClock};
+mod handle;
+pub use handle::{Gap, LogOptions, OverlapPolicy, PeriodTotal, Timers, BREAK_TASK_NAME};
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+mod rounding;
+pub use rounding::{Rounding, RoundingMode};
+mod project;
+pub use project::Project;
+mod validate;
+pub use validate::check_task;
+mod fsck;
+pub use fsck::Issue;
+mod filter;
+pub use filter::TaskFilter;
+mod search;
+pub use search::{MatchKind, TaskMatch};
+mod import;
+pub use import::{ImportPolicy, ImportSummary, ImportedLog};
+#[cfg(feature = "serde")]
+pub mod json;
+mod profile;
+pub use profile::{
+    create_profile, current_profile, list_profiles, set_data_dir, set_profile, switch_profile,
+    DEFAULT_PROFILE,
+};
+mod config;
+pub use config::{config_path, set_config, Config, Setting, SETTINGS};
+mod journal;
+pub use journal::{undone_entries, EntryKind, Journal, JournalEntry, RecordingStorage, TaskChange};
+
+fn data_path() -> Result<PathBuf, Error> {
     // Temporary code: migrate old folder if it exists
     // -------------
     let mut old_path = dirs::home_dir().unwrap();
@@ -38,150 +62,332 @@ fn get_repo() -> Result<Repo, Error> {
 
         let mut moved_path = dirs::data_dir().unwrap();
         moved_path.push(".timers");
-        std::fs::rename(&moved_path, &path).unwrap();
+        std::fs::rename(&moved_path, profile::default_data_dir()).unwrap();
     }
     // -------------
 
+    let path = profile::profile_data_dir()?;
+
     // ensure folder exists
     if !path.exists() {
         std::fs::create_dir_all(&path)?;
     }
 
+    Ok(path)
+}
+
+// For the commands working on the task files themselves
+fn get_repo() -> Result<Repo, Error> {
+    let path = data_path()?;
+    if StorageKind::detect(&path) != StorageKind::Text {
+        return Err(Error::Value(ValueError::new(
+            "This only works with plain text storage, see 'timers migrate'.",
+        )));
+    }
+
     Ok(Repo { path })
 }
 
+// Deleted tasks are kept there, see `timers restore`
+fn trash_dir() -> Result<PathBuf, Error> {
+    Ok(data_path()?.join(".trash"))
+}
+
+fn get_storage() -> Result<Box<dyn Storage>, Error> {
+    let path = data_path()?;
+    storage::open_storage(&path, StorageKind::detect(&path))
+}
+
+fn get_timers() -> Result<Timers, Error> {
+    Ok(Timers::new(get_storage()?))
+}
+
+// Runs an operation changing tasks, recording in the journal what it changed,
+// even if it failed halfway. The storage is locked until it is recorded.
+fn journaled<T>(
+    description: &str,
+    operation: impl FnOnce(&Timers) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let (storage, changes) = RecordingStorage::new(get_storage()?)?;
+    let timers = Timers::new(Box::new(storage));
+    let result = operation(&timers);
+
+    let changes: Vec<TaskChange> = changes
+        .take()
+        .into_iter()
+        .filter(|change| change.before != change.after)
+        .collect();
+    if !changes.is_empty() {
+        let journal = Journal::open(&data_path()?);
+        let recorded = journal.record(EntryKind::Do, description, changes, timers.now());
+        if result.is_ok() {
+            recorded?;
+        }
+    }
+
+    result
+}
+
+/// Opens the tasks in the given directory, with plain text
+/// or SQLite storage depending on what it contains.
+pub fn open_storage(path: &std::path::Path) -> Result<Box<dyn Storage>, Error> {
+    storage::open_storage(path, StorageKind::detect(path))
+}
+
+/// The storage used by the current profile.
+pub fn storage_kind() -> Result<StorageKind, Error> {
+    Ok(StorageKind::detect(&data_path()?))
+}
+
+/// Converts the tasks of the current profile to the given storage,
+/// returning the number of tasks converted. Earlier changes can then
+/// no longer be undone.
+pub fn migrate(to: StorageKind) -> Result<usize, Error> {
+    let path = data_path()?;
+    let count = storage::migrate(&path, to)?;
+
+    let _lock = open_storage(&path)?.lock()?;
+    let description = format!("Convert tasks to {} storage", to);
+    Journal::open(&path).record(EntryKind::Reset, &description, Vec::new(), chrono::Utc::now())?;
+    Ok(count)
+}
+
+// The free functions below work on the current profile with the
+// system time, see the Timers methods for their documentation.
+// Those changing tasks record their changes in the journal
 pub fn create_task(name: &str) -> Result<Task, Error> {
-    let repo = get_repo()?;
-    Ok(repo.create_task(name)?)
+    journaled(&format!("Create task '{}'", name), |timers| timers.create_task(name))
 }
 
 pub fn log_task_at(id: u32, at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
-    let repo = get_repo()?;
-    let mut task = repo.get_task(id)?;
-    repo.log_task(&mut task, at)?;
-    Ok(task)
+    journaled(&format!("Log on @{}", id), |timers| timers.log_task_at(id, at))
+}
+
+pub fn log_task_at_with(
+    id: u32,
+    at: chrono::DateTime<chrono::Utc>,
+    options: &LogOptions,
+) -> Result<Task, Error> {
+    journaled(&format!("Log on @{}", id), |timers| timers.log_task_at_with(id, at, options))
 }
 
 pub fn log_task(id: u32) -> Result<Task, Error> {
-    Ok(log_task_at(id, chrono::Utc::now())?)
+    journaled(&format!("Log on @{}", id), |timers| timers.log_task(id))
 }
 
 pub fn create_log_task_at(name: &str, at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
-    let repo = get_repo()?;
-    let mut task = repo.create_task(name)?;
-    repo.log_task(&mut task, at)?;
-    Ok(task)
+    journaled(&format!("Log on new task '{}'", name), |timers| {
+        timers.create_log_task_at(name, at)
+    })
+}
+
+pub fn create_log_task_at_with(
+    name: &str,
+    at: chrono::DateTime<chrono::Utc>,
+    options: &LogOptions,
+) -> Result<Task, Error> {
+    journaled(&format!("Log on new task '{}'", name), |timers| {
+        timers.create_log_task_at_with(name, at, options)
+    })
 }
 
 pub fn create_log_task(name: &str) -> Result<Task, Error> {
-    Ok(create_log_task_at(name, chrono::Utc::now())?)
+    journaled(&format!("Log on new task '{}'", name), |timers| timers.create_log_task(name))
 }
 
-pub fn get_current_log_task() -> Result<Option<Task>, Error> {
-    let repo = get_repo()?;
-    let mut tasks = repo.list_tasks()?;
+pub fn find_overlapping_logs(log: &Log) -> Result<Vec<(Task, Log)>, Error> {
+    get_timers()?.find_overlapping_logs(log)
+}
 
-    let mut found_id: Option<u32> = None;
-    for (id, task) in tasks.iter() {
-        if task.logging {
-            found_id = Some(*id);
-            break;
-        }
-    }
+pub fn add_log(id: u32, log: Log, policy: OverlapPolicy) -> Result<(Task, Vec<Log>), Error> {
+    journaled(&format!("Add a log to @{}", id), |timers| timers.add_log(id, log, policy))
+}
 
-    match found_id {
-        Some(id) => Ok(tasks.remove(&id)),
-        None => Ok(None),
-    }
+pub fn create_task_with_log(
+    name: &str,
+    log: Log,
+    policy: OverlapPolicy,
+) -> Result<(Task, Vec<Log>), Error> {
+    journaled(&format!("Add a log to new task '{}'", name), |timers| {
+        timers.create_task_with_log(name, log, policy)
+    })
+}
+
+pub fn add_break_log(log: Log, policy: OverlapPolicy) -> Result<(Task, Vec<Log>), Error> {
+    journaled("Add a break", |timers| timers.add_break_log(log, policy))
+}
+
+pub fn get_task(id: u32) -> Result<Task, Error> {
+    get_timers()?.get_task(id)
+}
+
+pub fn edit_log(id: u32, index: usize, log: Log) -> Result<Task, Error> {
+    journaled(&format!("Edit log @{}#{}", id, index), |timers| timers.edit_log(id, index, log))
+}
+
+pub fn delete_log(id: u32, index: usize) -> Result<(Task, Log), Error> {
+    journaled(&format!("Delete log @{}#{}", id, index), |timers| timers.delete_log(id, index))
+}
+
+pub fn set_log_note(id: u32, index: usize, note: Option<&str>) -> Result<Task, Error> {
+    journaled(&format!("Note on log @{}#{}", id, index), |timers| {
+        timers.set_log_note(id, index, note)
+    })
+}
+
+pub fn note_current_log(note: &str) -> Result<Task, Error> {
+    journaled("Note on the current log", |timers| timers.note_current_log(note))
+}
+
+pub fn move_log(id: u32, index: usize, target_id: u32) -> Result<(Task, Task), Error> {
+    journaled(&format!("Move log @{}#{} to @{}", id, index, target_id), |timers| {
+        timers.move_log(id, index, target_id)
+    })
+}
+
+pub fn split_log(id: u32, index: usize, at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
+    journaled(&format!("Split log @{}#{}", id, index), |timers| timers.split_log(id, index, at))
+}
+
+pub fn tag_task(id: u32, tags: &[String]) -> Result<Task, Error> {
+    journaled(&format!("Tag @{}", id), |timers| timers.tag_task(id, tags))
+}
+
+pub fn set_task_project(id: u32, project: Option<Project>) -> Result<Task, Error> {
+    journaled(&format!("Set the project of @{}", id), |timers| timers.set_task_project(id, project))
+}
+
+pub fn rename_task(id: u32, name: &str) -> Result<Task, Error> {
+    journaled(&format!("Rename @{} to '{}'", id, name), |timers| timers.rename_task(id, name))
+}
+
+pub fn set_task_archived(id: u32, archived: bool) -> Result<Task, Error> {
+    let action = if archived { "Archive" } else { "Unarchive" };
+    journaled(&format!("{} @{}", action, id), |timers| timers.set_task_archived(id, archived))
+}
+
+pub fn delete_task(id: u32) -> Result<Option<Task>, Error> {
+    let trash = trash_dir()?;
+    journaled(&format!("Delete @{}", id), |timers| timers.delete_task(id, &trash))
+}
+
+pub fn restore_task(id: u32) -> Result<Task, Error> {
+    let trash = trash_dir()?;
+    journaled(&format!("Restore @{}", id), |timers| timers.restore_task(id, &trash))
+}
+
+pub fn trashed_tasks() -> Result<Vec<Task>, Error> {
+    get_timers()?.trashed_tasks(&trash_dir()?)
+}
+
+/// Reverts the last change to the tasks not undone yet, see `Journal::undo`.
+pub fn undo() -> Result<JournalEntry, Error> {
+    let timers = get_timers()?;
+    Journal::open(&data_path()?).undo(timers.storage(), timers.now())
+}
+
+pub fn redo() -> Result<JournalEntry, Error> {
+    let timers = get_timers()?;
+    Journal::open(&data_path()?).redo(timers.storage(), timers.now())
+}
+
+pub fn history() -> Result<Vec<JournalEntry>, Error> {
+    Journal::open(&data_path()?).entries()
+}
+
+pub fn find_tasks(name: &str) -> Result<Vec<TaskMatch>, Error> {
+    get_timers()?.find_tasks(name)
+}
+
+pub fn get_current_log_task() -> Result<Option<Task>, Error> {
+    get_timers()?.get_current_log_task()
 }
 
 pub fn stop_current_task_at(at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
-    let repo = get_repo()?;
+    journaled("Stop the current task", |timers| timers.stop_current_task_at(at))
+}
 
-    match get_current_log_task()? {
-        Some(mut task) => {
-            repo.stop_task(&mut task, at)?;
-            Ok(task)
-        }
-        None => Err(Error::Value(ValueError::new(
-            "Not task currently being logged.",
-        ))),
-    }
+pub fn stop_current_task_at_with_note(
+    at: chrono::DateTime<chrono::Utc>,
+    note: Option<&str>,
+) -> Result<Task, Error> {
+    journaled("Stop the current task", |timers| {
+        timers.stop_current_task_at_with_note(at, note)
+    })
 }
 
 pub fn stop_current_task() -> Result<Task, Error> {
-    Ok(stop_current_task_at(chrono::Utc::now())?)
+    journaled("Stop the current task", |timers| timers.stop_current_task())
+}
+
+pub fn get_break_task() -> Result<Option<Task>, Error> {
+    get_timers()?.get_break_task()
+}
+
+pub fn pause_at(at: chrono::DateTime<chrono::Utc>) -> Result<(Task, Task), Error> {
+    journaled("Pause the current task", |timers| timers.pause_at(at))
+}
+
+pub fn pause_at_with_note(
+    at: chrono::DateTime<chrono::Utc>,
+    note: Option<&str>,
+) -> Result<(Task, Task), Error> {
+    journaled("Pause the current task", |timers| timers.pause_at_with_note(at, note))
+}
+
+pub fn resume_at(at: chrono::DateTime<chrono::Utc>) -> Result<Task, Error> {
+    journaled("Resume the last task", |timers| timers.resume_at(at))
 }
 
 pub fn get_all_tasks() -> Result<HashMap<u32, Task>, Error> {
-    let repo = get_repo()?;
-    Ok(repo.list_tasks()?)
+    get_timers()?.get_all_tasks()
 }
 
 pub fn get_all_tasks_between(
     start: chrono::DateTime<chrono::Utc>,
     end: chrono::DateTime<chrono::Utc>,
 ) -> Result<HashMap<u32, Task>, Error> {
-    let repo = get_repo()?;
-    let tasks = repo.list_tasks()?;
-
-    let mut filtered = HashMap::new();
-    for (id, task) in tasks {
-        for log in task.logs.iter() {
-            if log.start.ge(&start) && log.start.le(&end) {
-                filtered.insert(id, task);
-                break;
-            }
-
-            let log_end = match log.end {
-                Some(end) => end,
-                None => chrono::Utc::now(),
-            };
-            if log_end.ge(&start) && log_end.le(&end) {
-                filtered.insert(id, task);
-                break;
-            }
-        }
-    }
+    get_timers()?.get_all_tasks_between(start, end)
+}
 
-    Ok(filtered)
+pub fn get_period_totals(
+    periods: &[(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)],
+    filter: &TaskFilter,
+) -> Result<Vec<PeriodTotal>, Error> {
+    get_timers()?.get_period_totals(periods, filter)
 }
 
-// Returns all logs between start and end,
-// sorted chronologically
 pub fn get_all_logs_between(
     start: chrono::DateTime<chrono::Utc>,
     end: chrono::DateTime<chrono::Utc>,
 ) -> Result<Vec<(Task, Log)>, Error> {
-    let tasks = get_all_tasks_between(start, end)?;
-
-    let mut logs = BTreeMap::new();
-    for task in tasks.values() {
-        for log in task.logs.iter() {
-            if log.start > end || log.end() < start {
-                continue
-            }
+    get_timers()?.get_all_logs_between(start, end)
+}
 
-            logs.insert(log.start, (task.clone(), *log));
-        }
-    }
+pub fn import_logs(logs: Vec<ImportedLog>, policy: ImportPolicy) -> Result<ImportSummary, Error> {
+    journaled("Import logs", |timers| timers.import_logs(logs, policy))
+}
 
-    Ok(logs.values().map(|log| log.clone()).collect())
+pub fn get_gaps_between(
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+) -> Result<Vec<Gap>, Error> {
+    get_timers()?.get_gaps_between(start, end)
 }
 
 pub fn get_total_duration(
     start: chrono::DateTime<chrono::Utc>,
     end: chrono::DateTime<chrono::Utc>,
 ) -> Result<chrono::Duration, Error> {
-    let repo = get_repo()?;
-
-    let mut total_duration = chrono::Duration::seconds(0);
+    get_timers()?.get_total_duration(start, end)
+}
 
-    for task in repo.list_tasks()?.values() {
-        total_duration = total_duration.add(task.duration_between(start, end));
-    }
+pub fn replace_task(id: u32, content: &str) -> Result<(Option<Task>, Task), Error> {
+    journaled(&format!("Edit @{}", id), |timers| timers.replace_task(id, content))
+}
 
-    Ok(total_duration)
+pub fn task_content(id: u32) -> Result<String, Error> {
+    get_timers()?.task_content(id)
 }
 
 pub fn format_duration(duration: chrono::Duration) -> String {
@@ -225,7 +431,7 @@ pub fn format_duration_hours(duration: chrono::Duration) -> String {
 }
 
 pub fn find_start(tasks: &HashMap<u32, Task>) -> Result<chrono::DateTime<chrono::Utc>, Error> {
-    if tasks.len() == 0 {
+    if tasks.is_empty() {
         return Err(Error::Value(ValueError::new("There are no tasks.")));
     }
 
@@ -245,7 +451,7 @@ pub fn find_start(tasks: &HashMap<u32, Task>) -> Result<chrono::DateTime<chrono:
 }
 
 pub fn find_end(tasks: &HashMap<u32, Task>) -> Result<chrono::DateTime<chrono::Utc>, Error> {
-    if tasks.len() == 0 {
+    if tasks.is_empty() {
         return Err(Error::Value(ValueError::new("There are no tasks.")));
     }
 
@@ -264,8 +470,22 @@ pub fn find_end(tasks: &HashMap<u32, Task>) -> Result<chrono::DateTime<chrono::U
     Ok(end)
 }
 
-pub fn task_path(task: u32) -> PathBuf {
-    let mut path = data_path();
-    path.push(task.to_string());
-    path
+/// Checks the task files of the current profile, repairing
+/// the problems which can be safely repaired if asked to.
+pub fn fsck(repair: bool) -> Result<Vec<Issue>, Error> {
+    let repo = get_repo()?;
+    let _lock = repo.lock()?;
+    let issues = fsck::fsck(&repo, repair)?;
+
+    // Repairs are not journaled, undoing earlier changes could overwrite them
+    if repair && issues.iter().any(|issue| issue.repairable) {
+        let journal = Journal::open(&repo.path);
+        journal.record(EntryKind::Reset, "Repair task files", Vec::new(), chrono::Utc::now())?;
+    }
+    Ok(issues)
+}
+
+pub fn task_path(task: u32) -> Result<PathBuf, Error> {
+    let repo = get_repo()?;
+    Ok(repo.task_path(task))
 }
\ No newline at end of file
@@ -2,7 +2,8 @@ use std::collections::HashMap;
 
 use colored::*;
 use itertools::{Itertools, enumerate};
-use crate::util::parse_int;
+use crate::output;
+use crate::util::{config, format_log, format_project, format_tags, parse_int, task_filter};
 
 trait PrintTasks {
     fn print_header(&self);
@@ -33,12 +34,12 @@ struct ShortPrinter ();
 
 impl PrintTasks for ShortPrinter {
     fn print_header(&self) {
-        println!("{:<6} {:<36} {}", "ID", "TASK", "DURATION");
+        println!("{:<6} {:<36} DURATION", "ID", "TASK");
         println!("{}", "-".repeat(58));
     }
 
     fn print_ellipsis(&self) {
-        println!("{:<6} {:<36} {}", "...", "...", "...");
+        println!("{:<6} {:<36} ...", "...", "...");
     }
 
     fn print_task(&self, task: &timers::Task) {
@@ -64,61 +65,94 @@ struct LongPrinter ();
 impl PrintTasks for LongPrinter {
     fn print_header(&self) {
         println!(
-            "{:<6} {:<36} {:<14} {:<8} {:<6} {}",
-             "ID", "TASK", "DURATION", "STATUS", "LOGS", "LAST LOG"
+            "{:<6} {:<36} {:<14} {:<8} {:<6} {:<16} {:<16} TAGS",
+             "ID", "TASK", "DURATION", "STATUS", "LOGS", "LAST LOG", "PROJECT"
         );
-        println!("{}", "-".repeat(91));
+        println!("{}", "-".repeat(125));
     }
 
     fn print_ellipsis(&self) {
         println!(
-            "{:<6} {:<36} {:<14} {:<8} {:<6} {}",
-            "...", "...", "...", "...", "...", "..."
+            "{:<6} {:<36} {:<14} {:<8} {:<6} {:<16} {:<16} ...",
+            "...", "...", "...", "...", "...", "...", "..."
         );
     }
 
     fn print_task(&self, task: &timers::Task) {
         let last = task.logs.last().unwrap()
             .start.with_timezone(&chrono::Local)
-            .format("%a %b %d %H:%M").to_string();
+            .format(config().date_format()).to_string();
 
         match task.status() {
             timers::TaskStatus::Logging() => println!(
-                "{:<6} {:<36} {:<14} {:<8} {:<6} {}",
+                "{:<6} {:<36} {:<14} {:<8} {:<6} {:<16} {:<16} {}",
                 format!("@{}", task.id).yellow().bold(),
                 task.name.red().bold(),
                 timers::format_duration(task.duration()).bold(),
                 task.status_text().bold(),
                 format!("{}", task.logs.len()).bold(),
                 last.bold(),
+                format_project(task).bold(),
+                format_tags(task).cyan().bold(),
             ),
             timers::TaskStatus::Stopped() => println!(
-                "{:<6} {:<36} {:<14} {:<8} {:<6} {}",
+                "{:<6} {:<36} {:<14} {:<8} {:<6} {:<16} {:<16} {}",
                 format!("@{}", task.id),
                 task.name,
                 timers::format_duration(task.duration()),
-                task.status_text(),
+                if task.archived { "archived" } else { task.status_text() },
                 task.logs.len(),
                 last,
+                format_project(task),
+                format_tags(task).cyan(),
             ),
         }
+
+        // Notes are listed under the task, as they are what its logs were about
+        for log in task.logs.iter() {
+            if let Some(note) = &log.note {
+                println!("{:<6} {}  {}", "", format_log(log).dimmed(), note.italic());
+            }
+        }
     }
 }
 
 pub fn tasks_command(matches: &clap::ArgMatches) {
     let raw_num = matches.value_of("num").unwrap();
-    let num = parse_int(raw_num)
-        .unwrap_or_else(|_| {
-            println!("Invalid number of tasks: '{}'", raw_num);
-            std::process::exit(1);
-        }) as usize;
+    let num = match parse_int(raw_num) {
+        Ok(num) => num as usize,
+        Err(_) => return output::usage_error(&format!("Invalid number of tasks: '{}'", raw_num)),
+    };
+
+    let filter = match task_filter(matches) {
+        Some(filter) => filter,
+        None => return,
+    };
 
     let plain = matches.is_present("plain");
-    match timers::get_all_tasks() {
+    let all = matches.is_present("all");
+    let tasks = timers::get_all_tasks()
+        .map(|tasks| filter.apply(tasks))
+        .map(|tasks| {
+            tasks.into_iter().filter(|(_, task)| all || !(task.archived || task.is_break)).collect()
+        });
+    match tasks {
+        Ok(tasks) if !output::is_text() => print_task_records(tasks, num),
         Ok(tasks) => match matches.is_present("long") {
             true => LongPrinter{}.print_tasks(tasks, num, plain),
             false => ShortPrinter{}.print_tasks(tasks, num, plain),
         },
-        Err(err) => println!("Error retrieving tasks: {}", err)
+        Err(err) => output::fail_with("Error retrieving tasks", &err),
     }
-}
\ No newline at end of file
+}
+// The last num tasks, as for the printers
+fn print_task_records(tasks: HashMap<u32, timers::Task>, num: usize) {
+    let records: Vec<output::Record> = tasks
+        .keys()
+        .sorted()
+        .skip(tasks.len().saturating_sub(num))
+        .map(|id| output::task_record(&tasks[id]))
+        .collect();
+
+    output::print_records(&records);
+}
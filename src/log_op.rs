@@ -0,0 +1,156 @@
+use colored::*;
+
+use crate::output;
+use crate::util::{format_log, parse_log_ref, parse_time_on, resolve_task, print_status};
+
+fn log_date(log: &timers::Log) -> chrono::NaiveDate {
+    log.start.with_timezone(&chrono::Local).date_naive()
+}
+
+fn print_logs(task: &timers::Task) {
+    if !output::is_text() {
+        let records: Vec<output::Record> = task
+            .logs
+            .iter()
+            .enumerate()
+            .map(|(i, log)| output::log_record(task, Some(i + 1), log))
+            .collect();
+        return output::print_records(&records);
+    }
+
+    println!("@{}: {}", task.id, task.name);
+    for (i, log) in task.logs.iter().enumerate() {
+        let note = log.note.as_ref().map(|note| format!("  {}", note.italic()));
+        println!(
+            "#{:<4} {:<40} {}{}",
+            i + 1,
+            format_log(log),
+            timers::format_duration(log.duration()),
+            note.unwrap_or_default()
+        );
+    }
+}
+
+pub fn logs_command(matches: &clap::ArgMatches) {
+    let task_id = match resolve_task(matches.value_of("TASK").unwrap()) {
+        Some(task_id) => task_id,
+        None => return,
+    };
+
+    match timers::get_task(task_id) {
+        Ok(task) => print_logs(&task),
+        Err(err) => output::fail_with("Error reading task", &err),
+    }
+}
+
+pub fn log_edit_command(matches: &clap::ArgMatches) {
+    let (task_id, index) = match parse_log_ref(matches.value_of("LOG").unwrap()) {
+        Some(log_ref) => log_ref,
+        None => return,
+    };
+
+    let task = match timers::get_task(task_id) {
+        Ok(task) => task,
+        Err(err) => return output::fail_with("Error reading task", &err),
+    };
+    let mut log = match task.logs.get(index - 1) {
+        Some(log) => log.clone(),
+        None => {
+            let message = format!("Task @{} has no log #{}", task_id, index);
+            return output::fail(output::EXIT_FAILURE, &message);
+        }
+    };
+
+    let date = log_date(&log);
+    if let Some(raw_start) = matches.value_of("start") {
+        match parse_time_on(raw_start, date) {
+            Some(start) => log.start = start,
+            None => return,
+        }
+    }
+    if let Some(raw_end) = matches.value_of("end") {
+        match parse_time_on(raw_end, date) {
+            Some(end) => log.end = Some(end),
+            None => return,
+        }
+    }
+    // An empty note removes it
+    if let Some(note) = matches.value_of("note") {
+        log.note = Some(note.to_string());
+    }
+
+    let changed = format_log(&log);
+    match timers::edit_log(task_id, index, log) {
+        Ok(task) => {
+            output::note(&format!("Changed log #{} to {}", index, changed));
+            print_status(&task);
+        }
+        Err(err) => output::fail_with("Error editing log", &err),
+    }
+}
+
+pub fn log_delete_command(matches: &clap::ArgMatches) {
+    let (task_id, index) = match parse_log_ref(matches.value_of("LOG").unwrap()) {
+        Some(log_ref) => log_ref,
+        None => return,
+    };
+
+    match timers::delete_log(task_id, index) {
+        Ok((task, log)) => {
+            output::note(&format!(
+                "Deleted {} [{}]",
+                format_log(&log),
+                timers::format_duration(log.duration())
+            ));
+            print_status(&task);
+        }
+        Err(err) => output::fail_with("Error deleting log", &err),
+    }
+}
+
+pub fn log_move_command(matches: &clap::ArgMatches) {
+    let (task_id, index) = match parse_log_ref(matches.value_of("LOG").unwrap()) {
+        Some(log_ref) => log_ref,
+        None => return,
+    };
+    let target_id = match resolve_task(matches.value_of("TASK").unwrap()) {
+        Some(target_id) => target_id,
+        None => return,
+    };
+
+    match timers::move_log(task_id, index, target_id) {
+        Ok((_, target)) => {
+            output::note(&format!("Moved log @{}#{} to @{}", task_id, index, target_id));
+            print_status(&target);
+        }
+        Err(err) => output::fail_with("Error moving log", &err),
+    }
+}
+
+pub fn log_split_command(matches: &clap::ArgMatches) {
+    let (task_id, index) = match parse_log_ref(matches.value_of("LOG").unwrap()) {
+        Some(log_ref) => log_ref,
+        None => return,
+    };
+
+    let task = match timers::get_task(task_id) {
+        Ok(task) => task,
+        Err(err) => return output::fail_with("Error reading task", &err),
+    };
+    let date = match task.logs.get(index - 1) {
+        Some(log) => log_date(log),
+        None => {
+            let message = format!("Task @{} has no log #{}", task_id, index);
+            return output::fail(output::EXIT_FAILURE, &message);
+        }
+    };
+    let at = match parse_time_on(matches.value_of("at").unwrap(), date) {
+        Some(at) => at,
+        None => return,
+    };
+
+    match timers::split_log(task_id, index, at) {
+        Ok(task) => print_logs(&task),
+        Err(err) => output::fail_with("Error splitting log", &err),
+    }
+}
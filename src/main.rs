@@ -1,5 +1,3 @@
-use clap;
-
 mod basic_op;
 mod util;
 use basic_op::*;
@@ -9,55 +7,276 @@ mod report_op;
 use report_op::*;
 mod import_export_op;
 use import_export_op::*;
+mod profile_op;
+use profile_op::*;
+mod log_op;
+use log_op::*;
+mod fsck_op;
+use fsck_op::*;
+mod output;
+mod config_op;
+use config_op::*;
+mod task_op;
+use task_op::*;
+mod journal_op;
+use journal_op::*;
+mod gaps_op;
+use gaps_op::*;
 
 fn main() {
-    let matches = parse_args();
+    if !util::config().colors() {
+        colored::control::set_override(false);
+    }
+
+    let matches = parse_args(expand_alias(std::env::args().collect()));
+
+    if let Some(data_dir) = global_value(&matches, "data-dir") {
+        timers::set_data_dir(std::path::PathBuf::from(data_dir));
+    }
+    if let Some(profile) = global_value(&matches, "profile") {
+        timers::set_profile(profile);
+    }
+    match global_value(&matches, "output-format").map(output::OutputFormat::parse) {
+        Some(Some(format)) if format != output::OutputFormat::Json || cfg!(feature = "serde") => {
+            output::set_format(format)
+        }
+        Some(_) => {
+            output::usage_error("JSON support was not enabled at compile time.");
+            std::process::exit(output::exit_code());
+        }
+        None => {}
+    }
 
     match matches.subcommand_name() {
         Some("log") => log_command(matches.subcommand_matches("log").unwrap()),
+        Some("switch") => switch_command(matches.subcommand_matches("switch").unwrap()),
         Some("status") => status_command(matches.subcommand_matches("status").unwrap()),
         Some("stop") => stop_command(matches.subcommand_matches("stop").unwrap()),
+        Some("pause") => pause_command(matches.subcommand_matches("pause").unwrap()),
+        Some("resume") => resume_command(matches.subcommand_matches("resume").unwrap()),
+        Some("gaps") => gaps_command(matches.subcommand_matches("gaps").unwrap()),
+        Some("fill") => fill_command(matches.subcommand_matches("fill").unwrap()),
+        Some("note") => note_command(matches.subcommand_matches("note").unwrap()),
+        Some("add") => add_command(matches.subcommand_matches("add").unwrap()),
         Some("report") => {
             let submatches = matches.subcommand_matches("report").unwrap();
-            match submatches.subcommand_name() {
-                Some("days") => report_days_command(submatches),
+            match submatches.subcommand() {
+                ("days", Some(days_matches)) => report_days_command(days_matches),
+                ("weeks", Some(weeks_matches)) => report_weeks_command(weeks_matches),
+                ("months", Some(months_matches)) => report_months_command(months_matches),
+                ("range", Some(range_matches)) => report_range_command(range_matches),
+                ("tasks", Some(tasks_matches)) => report_tasks_command(tasks_matches),
+                ("projects", Some(projects_matches)) => report_projects_command(projects_matches),
+                ("breaks", Some(breaks_matches)) => report_breaks_command(breaks_matches),
                 _ => report_days_command(submatches),
             }
         }
         Some("tasks") => tasks_command(matches.subcommand_matches("tasks").unwrap()),
+        Some("projects") => projects_command(matches.subcommand_matches("projects").unwrap()),
+        Some("logs") => logs_command(matches.subcommand_matches("logs").unwrap()),
+        Some("log-edit") => log_edit_command(matches.subcommand_matches("log-edit").unwrap()),
+        Some("log-delete") => log_delete_command(matches.subcommand_matches("log-delete").unwrap()),
+        Some("log-move") => log_move_command(matches.subcommand_matches("log-move").unwrap()),
+        Some("log-split") => log_split_command(matches.subcommand_matches("log-split").unwrap()),
         Some("edit") => edit_command(matches.subcommand_matches("edit").unwrap()),
+        Some("rename") => rename_command(matches.subcommand_matches("rename").unwrap()),
+        Some("archive") => archive_command(matches.subcommand_matches("archive").unwrap()),
+        Some("delete") => delete_command(matches.subcommand_matches("delete").unwrap()),
+        Some("restore") => restore_command(matches.subcommand_matches("restore").unwrap()),
+        Some("undo") => undo_command(matches.subcommand_matches("undo").unwrap()),
+        Some("redo") => redo_command(matches.subcommand_matches("redo").unwrap()),
+        Some("history") => history_command(matches.subcommand_matches("history").unwrap()),
         Some("export") => export_command(matches.subcommand_matches("export").unwrap()),
+        Some("import") => import_command(matches.subcommand_matches("import").unwrap()),
+        Some("fsck") => fsck_command(matches.subcommand_matches("fsck").unwrap()),
+        Some("migrate") => migrate_command(matches.subcommand_matches("migrate").unwrap()),
+        Some("profiles") => profiles_command(matches.subcommand_matches("profiles").unwrap()),
+        Some("config") => config_command(matches.subcommand_matches("config").unwrap()),
         _ => {}
     }
+
+    std::process::exit(output::exit_code());
+}
+
+// Arguments of the commands starting to log on a task
+fn start_args<'a, 'b>() -> [clap::Arg<'a, 'b>; 6] {
+    [
+        clap::Arg::with_name("TASK")
+            .required(true)
+            .index(1)
+            .help(
+                "Name of the task to log, or @ID of an existing task. A name continues \
+                    logging on the task with that name, if any.",
+            ),
+        clap::Arg::with_name("new")
+            .long("new")
+            .help("Create a new task, even if a task already has this name."),
+        clap::Arg::with_name("TAGS")
+            .index(2)
+            .multiple(true)
+            .help("Tags to add to the task, written as +tag."),
+        clap::Arg::with_name("project")
+            .short("-p")
+            .long("--project")
+            .takes_value(true)
+            .value_name("PROJECT")
+            .help("Assign the task to a project, written as client/project or project."),
+        clap::Arg::with_name("AT")
+            .long("at")
+            .takes_value(true)
+            .value_name("TIME")
+            .allow_hyphen_values(true)
+            .help("Start logging at the specified time."),
+        note_arg("Note on what the new log is about."),
+    ]
+}
+
+fn gap_args<'a, 'b>() -> [clap::Arg<'a, 'b>; 2] {
+    [
+        clap::Arg::with_name("day")
+            .long("--day")
+            .takes_value(true)
+            .value_name("DAY")
+            .default_value("yesterday")
+            .allow_hyphen_values(true)
+            .help("The day to look at, as today, yesterday, an offset such as -2 or a date."),
+        clap::Arg::with_name("min")
+            .long("--min")
+            .takes_value(true)
+            .value_name("DURATION")
+            .default_value("5")
+            .help("Ignore gaps shorter than this, as minutes or hours:minutes."),
+    ]
+}
+
+fn note_arg<'a, 'b>(help: &'a str) -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name("note")
+        .short("-m")
+        .long("--message")
+        .takes_value(true)
+        .value_name("NOTE")
+        .help(help)
+}
+
+fn tag_filter_args<'a, 'b>() -> [clap::Arg<'a, 'b>; 2] {
+    [
+        clap::Arg::with_name("tag")
+            .long("--tag")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("TAG")
+            .help("Only include tasks with the given tag. Can be repeated."),
+        clap::Arg::with_name("not-tag")
+            .long("--not-tag")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("TAG")
+            .help("Exclude tasks with the given tag. Can be repeated."),
+    ]
+}
+
+// Global options can be given either before or after the subcommand
+fn global_value<'a>(matches: &'a clap::ArgMatches, name: &str) -> Option<&'a str> {
+    match matches.value_of(name) {
+        Some(value) => Some(value),
+        None => match matches.subcommand() {
+            (_, Some(submatches)) => global_value(submatches, name),
+            _ => None,
+        },
+    }
+}
+
+// The command can be an alias from the configuration, replaced by what it stands for
+fn expand_alias(args: Vec<String>) -> Vec<String> {
+    let mut position = 1;
+    while position < args.len() {
+        match args[position].as_str() {
+            "--data-dir" | "--profile" | "--output-format" => position += 2,
+            arg if arg.starts_with('-') => position += 1,
+            _ => break,
+        }
+    }
+
+    let command = match args.get(position) {
+        Some(command) if !COMMANDS.contains(&command.as_str()) => command,
+        _ => return args,
+    };
+    match util::config().alias(command) {
+        Some(expansion) => {
+            let mut expanded = args[..position].to_vec();
+            expanded.extend(expansion.split_whitespace().map(|arg| arg.to_string()));
+            expanded.extend(args[position + 1..].iter().cloned());
+            expanded
+        }
+        None => args,
+    }
 }
 
-fn parse_args() -> clap::ArgMatches<'static> {
-    return clap::App::new("timers")
+// Names of the subcommands and their aliases, which aliases cannot replace
+const COMMANDS: [&str; 34] = [
+    "log", "start", "switch", "status", "stop", "pause", "resume", "note", "gaps", "fill", "add",
+    "report", "tasks", "projects", "logs", "log-edit", "log-delete", "log-move", "log-split",
+    "edit", "rename", "archive", "delete", "restore", "undo", "redo", "history", "export",
+    "import", "fsck", "migrate", "profiles", "config", "help",
+];
+
+fn parse_args(args: Vec<String>) -> clap::ArgMatches<'static> {
+    let config = util::config();
+    clap::App::new("timers")
         .author("Francesco Pasa <francescopasa@gmail.com>")
         .version(clap::crate_version!())
         .about("Track time spent on tasks")
         .setting(clap::AppSettings::ArgRequiredElseHelp)
+        .arg(
+            clap::Arg::with_name("data-dir")
+                .long("--data-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .global(true)
+                .help("Directory where the data is stored. Can also be set with TIMERS_DIR."),
+        )
+        .arg(
+            clap::Arg::with_name("output-format")
+                .long("--output-format")
+                .takes_value(true)
+                .value_name("FORMAT")
+                .possible_values(&["text", "json", "tsv"])
+                .global(true)
+                .help("Print the result as JSON or tab separated values, for scripts."),
+        )
+        .arg(
+            clap::Arg::with_name("profile")
+                .long("--profile")
+                .takes_value(true)
+                .value_name("NAME")
+                .global(true)
+                .help("Use the given profile instead of the default one."),
+        )
         .subcommand(
             clap::SubCommand::with_name("log")
                 .alias("start")
                 .about("Log time on a task")
-                .arg(clap::Arg::with_name("TASK")
-                    .required(true)
-                    .index(1)
-                    .help(
-                        "Name of the task to log, or ID of an existing task, \
-                            to continue logging on an existing task.",
-                    )
+                .args(&start_args())
+                .arg(
+                    clap::Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Stop the task being logged, if any, without asking."),
                 )
                 .arg(
-                    clap::Arg::with_name("AT")
-                        .long("at")
-                        .takes_value(true)
-                        .value_name("TIME")
-                        .allow_hyphen_values(true)
-                        .help("Start logging at the specified time."),
+                    clap::Arg::with_name("no-stop")
+                        .long("no-stop")
+                        .conflicts_with("yes")
+                        .help("Do not stop the task being logged, if any: fail instead."),
                 ),
         )
+        .subcommand(
+            clap::SubCommand::with_name("switch")
+                .about("Stop the current task and start logging on another one at the same time")
+                .args(&start_args()),
+        )
         .subcommand(
             clap::SubCommand::with_name("status")
                 .about("Get logging status")
@@ -68,8 +287,9 @@ fn parse_args() -> clap::ArgMatches<'static> {
                         .takes_value(true)
                         .min_values(0)
                         .max_values(1)
-                        .default_value("5")
-                        .help("Keep watching the status, for a GUI like effect."),
+                        .default_value(config.get("status.watch_interval").unwrap())
+                        .help("Keep watching the status, for a GUI like effect, updating \
+                            every given minutes."),
                 )
                 .arg(
                     clap::Arg::with_name("timeline")
@@ -88,24 +308,242 @@ fn parse_args() -> clap::ArgMatches<'static> {
                         .value_name("TIME")
                         .allow_hyphen_values(true)
                         .help("Stop logging at the specified time."),
+                )
+                .arg(note_arg("Note on what the stopped log was about.")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("pause")
+                .about("Stop logging on the current task and start a break")
+                .arg(
+                    clap::Arg::with_name("AT")
+                        .long("at")
+                        .takes_value(true)
+                        .value_name("TIME")
+                        .allow_hyphen_values(true)
+                        .help("Start the break at the specified time."),
+                )
+                .arg(note_arg("Note on the break.")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("resume")
+                .about("End the break and log again on the task paused")
+                .arg(
+                    clap::Arg::with_name("AT")
+                        .long("at")
+                        .takes_value(true)
+                        .value_name("TIME")
+                        .allow_hyphen_values(true)
+                        .help("Resume at the specified time."),
                 ),
         )
+        .subcommand(
+            clap::SubCommand::with_name("gaps")
+                .about("List the untracked time in the working hours of a day")
+                .args(&gap_args()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("fill")
+                .about("Ask what each untracked time of a day was spent on, and log it")
+                .args(&gap_args()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("note")
+                .about("Set the note of the current log")
+                .arg(clap::Arg::with_name("NOTE")
+                    .required(true)
+                    .index(1)
+                    .help("What the current log is about, replacing its note if any."),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("add")
+                .about("Add a log to a task after the fact")
+                .arg(clap::Arg::with_name("TASK")
+                    .required(true)
+                    .index(1)
+                    .help("The task to add the log to, as @ID or name."),
+                )
+                .arg(
+                    clap::Arg::with_name("from")
+                        .long("--from")
+                        .takes_value(true)
+                        .required(true)
+                        .value_name("TIME")
+                        .allow_hyphen_values(true)
+                        .help("Start of the log."),
+                )
+                .arg(
+                    clap::Arg::with_name("to")
+                        .long("--to")
+                        .takes_value(true)
+                        .value_name("TIME")
+                        .allow_hyphen_values(true)
+                        .help("End of the log."),
+                )
+                .arg(
+                    clap::Arg::with_name("duration")
+                        .long("--duration")
+                        .takes_value(true)
+                        .value_name("DURATION")
+                        .help("Duration of the log, as minutes or hours:minutes."),
+                )
+                .group(
+                    clap::ArgGroup::with_name("end")
+                        .args(&["to", "duration"])
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("trim")
+                        .long("--trim")
+                        .help("Only add the parts of the log which do not overlap existing logs, \
+                        instead of refusing to add it."),
+                )
+                .arg(note_arg("Note on what the log is about.")),
+        )
         .subcommand(
             clap::SubCommand::with_name("report")
                 .about("Report statistics on the tasks")
                 .subcommand(clap::SubCommand::with_name("days")
-                    .about("Report statistics on days.")
+                    .about("Report statistics on the days of a week.")
+                    .arg(
+                        clap::Arg::with_name("week")
+                            .long("--week")
+                            .takes_value(true)
+                            .allow_hyphen_values(true)
+                            .help("Week to report, either relative to this one (-1 is last week) \
+                            or a date in the week. Default is this week."),
+                    )
+                )
+                .subcommand(clap::SubCommand::with_name("weeks")
+                    .about("Report statistics on the weeks of a month.")
+                    .arg(
+                        clap::Arg::with_name("month")
+                            .long("--month")
+                            .takes_value(true)
+                            .allow_hyphen_values(true)
+                            .help("Month to report, either relative to this one (-1 is last month) \
+                            or as YYYY-MM. Default is this month."),
+                    )
+                )
+                .subcommand(clap::SubCommand::with_name("months")
+                    .about("Report statistics on the months of a year.")
+                    .arg(
+                        clap::Arg::with_name("year")
+                            .long("--year")
+                            .takes_value(true)
+                            .allow_hyphen_values(true)
+                            .help("Year to report, either relative to this one (-1 is last year) \
+                            or as YYYY. Default is this year."),
+                    )
+                )
+                .subcommand(clap::SubCommand::with_name("range")
+                    .about("Report statistics on each day between two dates.")
+                    .arg(
+                        clap::Arg::with_name("from")
+                            .long("--from")
+                            .takes_value(true)
+                            .required(true)
+                            .allow_hyphen_values(true)
+                            .help("Start of the range."),
+                    )
+                    .arg(
+                        clap::Arg::with_name("to")
+                            .long("--to")
+                            .takes_value(true)
+                            .required(true)
+                            .allow_hyphen_values(true)
+                            .help("End of the range, not included."),
+                    )
+                )
+                .subcommand(clap::SubCommand::with_name("tasks")
+                    .about("Report time logged on each task. Default is this week.")
+                    .arg(
+                        clap::Arg::with_name("from")
+                            .long("--from")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .allow_hyphen_values(true)
+                            .help("Report starting from the given date and time."),
+                    )
+                    .arg(
+                        clap::Arg::with_name("to")
+                            .long("--to")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .allow_hyphen_values(true)
+                            .help("Report up to the given date and time."),
+                    )
+                    .arg(
+                        clap::Arg::with_name("by")
+                            .long("--by")
+                            .takes_value(true)
+                            .possible_values(&["day"])
+                            .help("Split the time logged on each task by day."),
+                    )
+                )
+                .subcommand(clap::SubCommand::with_name("projects")
+                    .about("Report time logged on each project, by client. Default is this week.")
+                    .arg(
+                        clap::Arg::with_name("from")
+                            .long("--from")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .help("Report starting from the given date and time."),
+                    )
+                    .arg(
+                        clap::Arg::with_name("to")
+                            .long("--to")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .help("Report up to the given date and time."),
+                    )
+                )
+                .subcommand(clap::SubCommand::with_name("breaks")
+                    .about("Report the work and breaks of each day, checking the minimum break. \
+                        Default is this week.")
+                    .arg(
+                        clap::Arg::with_name("from")
+                            .long("--from")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .help("Report starting from the given date and time."),
+                    )
+                    .arg(
+                        clap::Arg::with_name("to")
+                            .long("--to")
+                            .takes_value(true)
+                            .number_of_values(1)
+                            .help("Report up to the given date and time."),
+                    )
                 )
                 .arg(
                     clap::Arg::with_name("plain")
                         .long("--plain")
+                        .global(true)
                         .help("Omit printing table header and totals."),
                 )
                 .arg(
                     clap::Arg::with_name("tot-hours")
                         .long("--tot-hours")
+                        .global(true)
                         .help("Print totals in hours."),
-                ),
+                )
+                .args(&tag_filter_args().map(|arg| arg.global(true))),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("projects")
+                .about("Print projects with the time logged on them")
+                .arg(
+                    clap::Arg::with_name("plain")
+                        .long("--plain")
+                        .help("Omit printing table header and totals."),
+                )
+                .arg(
+                    clap::Arg::with_name("tot-hours")
+                        .long("--tot-hours")
+                        .help("Print durations in hours."),
+                )
+                .args(&tag_filter_args()),
         )
         .subcommand(
             clap::SubCommand::with_name("tasks")
@@ -120,14 +558,103 @@ fn parse_args() -> clap::ArgMatches<'static> {
                     clap::Arg::with_name("num")
                         .short("-n")
                         .long("--num")
-                        .default_value("30")
-                        .help("Display the last <num> tasks. Default 30."),
+                        .default_value(config.get("tasks.num").unwrap())
+                        .help("Display the last <num> tasks."),
                 )
                 .arg(
                     clap::Arg::with_name("plain")
                         .long("--plain")
                         .help("Omit printing table header."),
-                ),
+                )
+                .arg(
+                    clap::Arg::with_name("all")
+                        .short("-a")
+                        .long("--all")
+                        .help("Also display archived tasks and the break task."),
+                )
+                .args(&tag_filter_args()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("logs")
+                .about("List the logs of a task with their indices")
+                .arg(clap::Arg::with_name("TASK")
+                    .required(true)
+                    .index(1)
+                    .help("The task, as @ID or name."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("log-edit")
+                .about("Change the start, end or note of a log")
+                .arg(clap::Arg::with_name("LOG")
+                    .required(true)
+                    .index(1)
+                    .help("The log to change, as @TASK#INDEX."),
+                )
+                .arg(
+                    clap::Arg::with_name("start")
+                        .long("--start")
+                        .takes_value(true)
+                        .value_name("TIME")
+                        .allow_hyphen_values(true)
+                        .help("New start of the log. Times of the day refer to the day of the log."),
+                )
+                .arg(
+                    clap::Arg::with_name("end")
+                        .long("--end")
+                        .takes_value(true)
+                        .value_name("TIME")
+                        .allow_hyphen_values(true)
+                        .help("New end of the log. Times of the day refer to the day of the log."),
+                )
+                .arg(note_arg("New note of the log, an empty note removes it."))
+                .group(
+                    clap::ArgGroup::with_name("change")
+                        .args(&["start", "end", "note"])
+                        .multiple(true)
+                        .required(true),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("log-delete")
+                .about("Delete a log")
+                .arg(clap::Arg::with_name("LOG")
+                    .required(true)
+                    .index(1)
+                    .help("The log to delete, as @TASK#INDEX."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("log-move")
+                .about("Move a log to another task")
+                .arg(clap::Arg::with_name("LOG")
+                    .required(true)
+                    .index(1)
+                    .help("The log to move, as @TASK#INDEX."),
+                )
+                .arg(clap::Arg::with_name("TASK")
+                    .required(true)
+                    .index(2)
+                    .help("The task to move the log to, as @ID or name."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("log-split")
+                .about("Split a log in two")
+                .arg(clap::Arg::with_name("LOG")
+                    .required(true)
+                    .index(1)
+                    .help("The log to split, as @TASK#INDEX."),
+                )
+                .arg(
+                    clap::Arg::with_name("at")
+                        .long("--at")
+                        .takes_value(true)
+                        .required(true)
+                        .value_name("TIME")
+                        .allow_hyphen_values(true)
+                        .help("Where to split the log. Times of the day refer to the day of the log."),
+                )
         )
         .subcommand(
             clap::SubCommand::with_name("edit")
@@ -135,10 +662,105 @@ fn parse_args() -> clap::ArgMatches<'static> {
                 .arg(clap::Arg::with_name("TASK")
                     .required(true)
                     .index(1)
-                    .help("The ID of the task to be edited.",
+                    .help("The task to be edited, as @ID or name.",
                 )
             )
         )
+        .subcommand(
+            clap::SubCommand::with_name("rename")
+                .about("Rename a task")
+                .arg(clap::Arg::with_name("TASK")
+                    .required(true)
+                    .index(1)
+                    .help("The task to rename, as @ID or name."),
+                )
+                .arg(clap::Arg::with_name("NAME")
+                    .required(true)
+                    .index(2)
+                    .help("The new name of the task."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("archive")
+                .about("Hide a task from 'timers tasks', keeping it in reports")
+                .arg(clap::Arg::with_name("TASK")
+                    .required(true)
+                    .index(1)
+                    .help("The task to archive, as @ID or name."),
+                )
+                .arg(
+                    clap::Arg::with_name("undo")
+                        .long("--undo")
+                        .help("Show the task again."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("delete")
+                .about("Delete a task, keeping it in the trash")
+                .arg(clap::Arg::with_name("TASK")
+                    .required(true)
+                    .index(1)
+                    .help("The task to delete, as @ID or name."),
+                )
+                .arg(
+                    clap::Arg::with_name("yes")
+                        .short("-y")
+                        .long("--yes")
+                        .help("Delete the task without asking."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("restore")
+                .about("Restore a deleted task, or list the deleted tasks")
+                .arg(clap::Arg::with_name("TASK")
+                    .index(1)
+                    .help("The ID of the deleted task, as @ID."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("undo")
+                .about("Undo the last changes to the tasks")
+                .arg(
+                    clap::Arg::with_name("count")
+                        .short("-n")
+                        .takes_value(true)
+                        .default_value("1")
+                        .help("Number of changes to undo."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("redo")
+                .about("Redo the last changes undone")
+                .arg(
+                    clap::Arg::with_name("count")
+                        .short("-n")
+                        .takes_value(true)
+                        .default_value("1")
+                        .help("Number of changes to redo."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("history")
+                .about("Print the changes made to the tasks")
+                .arg(clap::Arg::with_name("TASK")
+                    .index(1)
+                    .help("Only print the changes to this task, as @ID or name."),
+                )
+                .arg(
+                    clap::Arg::with_name("num")
+                        .short("-n")
+                        .long("--num")
+                        .takes_value(true)
+                        .default_value("20")
+                        .help("Print the last <num> changes."),
+                )
+                .arg(
+                    clap::Arg::with_name("long")
+                        .short("-l")
+                        .long("--long")
+                        .help("Also print the lines of the tasks removed and added."),
+                )
+        )
         .subcommand(
             clap::SubCommand::with_name("export")
                 .about("Export tasks to CSV")
@@ -146,10 +768,52 @@ fn parse_args() -> clap::ArgMatches<'static> {
                     clap::Arg::with_name("OBJECT")
                         .required(true)
                         .index(1)
-                        .possible_values(&["logs", "tasks"])
+                        .possible_values(&["logs", "tasks", "timesheet"])
                         .help(
-                            "Either 'logs', to export log information or 'tasks' \
-                        to export task information.",
+                            "Either 'logs', to export log information, 'tasks' \
+                        to export task information or 'timesheet' to export a grid \
+                        of the hours logged on each task in each day of a week.",
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name("week")
+                        .long("--week")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .allow_hyphen_values(true)
+                        .help(
+                            "Week of the timesheet, either relative to this one (-1 is last week) \
+                        or a date in the week. Default is this week.",
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name("rounding")
+                        .long("--rounding")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .possible_values(&["none", "nearest", "up", "down"])
+                        .default_value(config.get("rounding.mode").unwrap())
+                        .help("How to round the hours of the timesheet."),
+                )
+                .arg(
+                    clap::Arg::with_name("increment")
+                        .long("--increment")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .possible_values(&["6", "15", "30"])
+                        .default_value(config.get("rounding.increment").unwrap())
+                        .help("Round timesheet hours to multiples of this many minutes."),
+                )
+                .arg(
+                    clap::Arg::with_name("round-per")
+                        .long("--round-per")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .possible_values(&["log", "day"])
+                        .default_value(config.get("rounding.per").unwrap())
+                        .help(
+                            "Round each log separately, or the total of each task \
+                        in each day.",
                         ),
                 )
                 .arg(
@@ -158,6 +822,7 @@ fn parse_args() -> clap::ArgMatches<'static> {
                         .long("--output")
                         .takes_value(true)
                         .number_of_values(1)
+                        .value_name("FILE")
                         .help("Export to the given file instead of printing to standard output."),
                 )
                 .arg(
@@ -169,6 +834,19 @@ fn parse_args() -> clap::ArgMatches<'static> {
                         .default_value(",")
                         .help("Field delimiter to use. Only a single character is allowed."),
                 )
+                .arg(
+                    clap::Arg::with_name("format")
+                        .short("-f")
+                        .long("--format")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .possible_values(&["csv", "json"])
+                        .default_value("csv")
+                        .help(
+                            "Output format. JSON always contains the tasks with all their logs, \
+                        whatever is being exported.",
+                        ),
+                )
                 .arg(
                     clap::Arg::with_name("from")
                         .long("--from")
@@ -182,7 +860,120 @@ fn parse_args() -> clap::ArgMatches<'static> {
                         .takes_value(true)
                         .number_of_values(1)
                         .help("Export only up to the given date and time."),
+                )
+                .args(&tag_filter_args()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("import")
+                .about("Import logs from CSV")
+                .arg(
+                    clap::Arg::with_name("OBJECT")
+                        .required(true)
+                        .index(1)
+                        .possible_values(&["logs"])
+                        .help("What to import, currently only 'logs' exported with 'export'."),
+                )
+                .arg(
+                    clap::Arg::with_name("FILE")
+                        .required(true)
+                        .index(2)
+                        .help("The CSV or JSON file to import."),
+                )
+                .arg(
+                    clap::Arg::with_name("format")
+                        .short("-f")
+                        .long("--format")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .possible_values(&["csv", "json"])
+                        .help("Input format. By default JSON is used for files ending in .json."),
+                )
+                .arg(
+                    clap::Arg::with_name("delimiter")
+                        .short("-d")
+                        .long("--delimiter")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .default_value(",")
+                        .help("Field delimiter to use. Only a single character is allowed."),
+                )
+                .arg(
+                    clap::Arg::with_name("on-conflict")
+                        .long("--on-conflict")
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .possible_values(&["skip", "merge", "renumber"])
+                        .default_value("skip")
+                        .help(
+                            "What to do when a task ID is already used by a task with another \
+                        name: skip the task, merge into the existing task (also joining \
+                        overlapping logs), or renumber the imported task.",
+                        ),
                 ),
         )
-        .get_matches();
+        .subcommand(
+            clap::SubCommand::with_name("fsck")
+                .about("Check the task files for problems")
+                .arg(
+                    clap::Arg::with_name("repair")
+                        .long("--repair")
+                        .help("Repair the problems which can be repaired without losing logged time."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("migrate")
+                .about("Convert the tasks of the profile to another storage")
+                .arg(
+                    clap::Arg::with_name("to")
+                        .long("--to")
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(&["text", "sqlite"])
+                        .help("The storage to convert to. SQLite requires timers to be built \
+                        with the sqlite feature."),
+                )
+        )
+        .subcommand(
+            clap::SubCommand::with_name("profiles")
+                .about("List, create and switch profiles")
+                .subcommand(
+                    clap::SubCommand::with_name("create")
+                        .about("Create a new profile")
+                        .arg(clap::Arg::with_name("NAME").required(true).index(1)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("switch")
+                        .about("Make the given profile the default one")
+                        .arg(clap::Arg::with_name("NAME").required(true).index(1)),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("config")
+                .about("List, get and set the settings of the configuration file")
+                .subcommand(clap::SubCommand::with_name("list").about("List all settings"))
+                .subcommand(
+                    clap::SubCommand::with_name("get")
+                        .about("Print the value of a setting")
+                        .arg(clap::Arg::with_name("KEY").required(true).index(1)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("set")
+                        .about("Change a setting, or define an alias as aliases.NAME")
+                        .arg(clap::Arg::with_name("KEY").required(true).index(1))
+                        .arg(
+                            clap::Arg::with_name("VALUE")
+                                .required(true)
+                                .index(2)
+                                .allow_hyphen_values(true),
+                        ),
+                ),
+        )
+        .get_matches_from_safe(args)
+        .unwrap_or_else(|err| match err.kind {
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => err.exit(),
+            _ => {
+                eprintln!("{}", err.message);
+                std::process::exit(output::EXIT_USAGE);
+            }
+        })
 }
@@ -0,0 +1,80 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+use crate::errors::{Error, ValueError};
+use crate::repo::Task;
+use crate::storage::{missing_task, Storage, StorageLock};
+
+/// Keeps tasks in memory only, for tests and for embedding `timers`
+/// in applications with their own persistence.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    tasks: RefCell<HashMap<u32, Task>>,
+    last_id: Cell<u32>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    pub fn from_tasks(tasks: HashMap<u32, Task>) -> MemoryStorage {
+        MemoryStorage {
+            last_id: Cell::new(tasks.keys().copied().max().unwrap_or(0)),
+            tasks: RefCell::new(tasks),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn list_tasks(&self) -> Result<HashMap<u32, Task>, Error> {
+        Ok(self.tasks.borrow().clone())
+    }
+
+    fn get_task(&self, id: u32) -> Result<Task, Error> {
+        self.tasks.borrow().get(&id).cloned().ok_or_else(|| missing_task(id))
+    }
+
+    fn create_task_with_id(&self, id: u32, name: &str) -> Result<Task, Error> {
+        if self.tasks.borrow().contains_key(&id) {
+            return Err(Error::Value(ValueError::new(
+                format!("Task @{} already exists.", id).as_str(),
+            )));
+        }
+
+        let task = Task {
+            id,
+            path: std::path::PathBuf::new(),
+            name: name.to_string(),
+            project: None,
+            tags: Vec::new(),
+            archived: false,
+            is_break: false,
+            logs: Vec::new(),
+            logging: false,
+        };
+        self.tasks.borrow_mut().insert(id, task.clone());
+        self.last_id.set(self.last_id.get().max(id));
+
+        Ok(task)
+    }
+
+    fn replace_task(&self, task: &Task) -> Result<(), Error> {
+        self.tasks.borrow_mut().insert(task.id, task.clone());
+        self.last_id.set(self.last_id.get().max(task.id));
+        Ok(())
+    }
+
+    fn delete_task(&self, id: u32) -> Result<(), Error> {
+        self.tasks.borrow_mut().remove(&id).map(|_| ()).ok_or_else(|| missing_task(id))
+    }
+
+    fn next_id(&self) -> Result<u32, Error> {
+        let max_id = self.tasks.borrow().keys().copied().max().unwrap_or(0);
+        Ok(max_id.max(self.last_id.get()) + 1)
+    }
+
+    fn lock(&self) -> Result<StorageLock, Error> {
+        Ok(StorageLock::none())
+    }
+}
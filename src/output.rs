@@ -0,0 +1,272 @@
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+
+/// Exit code when a command could not do what was asked, e.g. a missing task.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid arguments, e.g. a time that cannot be parsed.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code when the tasks could not be read or written.
+pub const EXIT_STORAGE: i32 = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Tsv,
+}
+
+static FORMAT: Mutex<OutputFormat> = Mutex::new(OutputFormat::Text);
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+
+impl OutputFormat {
+    pub fn parse(raw: &str) -> Option<OutputFormat> {
+        match raw {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "tsv" => Some(OutputFormat::Tsv),
+            _ => None,
+        }
+    }
+}
+
+pub fn set_format(format: OutputFormat) {
+    *FORMAT.lock().unwrap() = format;
+}
+
+pub fn format() -> OutputFormat {
+    *FORMAT.lock().unwrap()
+}
+
+pub fn is_text() -> bool {
+    format() == OutputFormat::Text
+}
+
+/// The code to exit with, the one of the last error printed if any.
+pub fn exit_code() -> i32 {
+    EXIT_CODE.load(Ordering::SeqCst)
+}
+
+pub fn set_exit_code(code: i32) {
+    EXIT_CODE.store(code, Ordering::SeqCst);
+}
+
+/// Prints an error and records the exit code. Errors are printed as the
+/// rest of the output: JSON on standard output, TSV on standard error.
+pub fn fail(code: i32, message: &str) {
+    set_exit_code(code);
+
+    match format() {
+        OutputFormat::Text => println!("{}", message),
+        OutputFormat::Json => print_json(&error_record(code, message)),
+        OutputFormat::Tsv => eprintln!("{}", message),
+    }
+}
+
+/// Prints an error of the library, prefixed with what was being done.
+pub fn fail_with(context: &str, err: &timers::Error) {
+    let code = match err {
+        timers::Error::Value(_) => EXIT_FAILURE,
+        timers::Error::Io(_) | timers::Error::Corrupt { .. } => EXIT_STORAGE,
+    };
+    fail(code, &format!("{}: {}", context, err));
+}
+
+pub fn usage_error(message: &str) {
+    fail(EXIT_USAGE, message);
+}
+
+/// Prints a message meant for humans, which would get in the way of
+/// structured output and then goes to standard error.
+pub fn note(message: &str) {
+    if is_text() {
+        println!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Field {
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Time(chrono::DateTime<chrono::Utc>),
+    List(Vec<String>),
+    Bool(bool),
+    Null,
+}
+
+impl Field {
+    pub fn text(text: &str) -> Field {
+        Field::Text(text.to_string())
+    }
+
+    /// The value as text, null if missing.
+    pub fn opt<T: ToString>(value: Option<T>) -> Field {
+        value.map_or(Field::Null, |value| Field::Text(value.to_string()))
+    }
+
+    pub fn count(count: usize) -> Field {
+        Field::Int(count as i64)
+    }
+
+    pub fn time(time: Option<chrono::DateTime<chrono::Utc>>) -> Field {
+        time.map_or(Field::Null, Field::Time)
+    }
+
+    pub fn seconds(duration: chrono::Duration) -> Field {
+        Field::Int(duration.num_seconds())
+    }
+}
+
+/// An object of the structured output, as field name and value pairs.
+pub type Record = Vec<(&'static str, Field)>;
+
+pub fn print_record(record: &Record) {
+    match format() {
+        OutputFormat::Json => print_json(record),
+        _ => print_tsv(&[record], None),
+    }
+}
+
+/// Prints nothing found: `null` in JSON, no line in TSV.
+pub fn print_no_record() {
+    if format() == OutputFormat::Json {
+        println!("null");
+    }
+}
+
+pub fn print_records(records: &[Record]) {
+    match format() {
+        OutputFormat::Json => print_json_list(records),
+        _ => print_tsv(&records.iter().collect::<Vec<_>>(), None),
+    }
+}
+
+/// Prints the rows of a report and its total, as an object with `rows`
+/// and `total` in JSON, or with the total as last line in TSV.
+pub fn print_report(rows: &[Record], total: &Record) {
+    match format() {
+        OutputFormat::Json => print_json_report(rows, total),
+        _ => print_tsv(&rows.iter().collect::<Vec<_>>(), Some(total)),
+    }
+}
+
+pub fn task_record(task: &timers::Task) -> Record {
+    let last_log = task.logs.last();
+    let open_log = last_log.filter(|log| log.end.is_none());
+
+    vec![
+        ("id", Field::Int(task.id as i64)),
+        ("name", Field::text(&task.name)),
+        ("project", Field::opt(task.project.as_ref())),
+        ("tags", Field::List(task.tags.clone())),
+        ("status", Field::text(task.status_text())),
+        ("archived", Field::Bool(task.archived)),
+        ("break", Field::Bool(task.is_break)),
+        ("logs", Field::count(task.logs.len())),
+        ("duration_seconds", Field::seconds(task.duration())),
+        ("last_log_start", Field::time(last_log.map(|log| log.start))),
+        ("logging_since", Field::time(open_log.map(|log| log.start))),
+    ]
+}
+
+pub fn log_record(task: &timers::Task, index: Option<usize>, log: &timers::Log) -> Record {
+    let mut record = vec![
+        ("task_id", Field::Int(task.id as i64)),
+        ("task_name", Field::text(&task.name)),
+    ];
+    if let Some(index) = index {
+        record.push(("index", Field::count(index)));
+    }
+    record.push(("start", Field::Time(log.start)));
+    record.push(("end", Field::time(log.end)));
+    record.push(("duration_seconds", Field::seconds(log.duration())));
+    record.push(("note", Field::opt(log.note.as_ref())));
+    record
+}
+
+fn error_record(code: i32, message: &str) -> Record {
+    vec![("error", Field::text(message)), ("exit_code", Field::Int(code as i64))]
+}
+
+#[cfg(feature = "serde")]
+fn json_value(record: &Record) -> serde_json::Value {
+    let mut object = serde_json::Map::new();
+    for (name, field) in record.iter() {
+        let value = match field {
+            Field::Int(value) => serde_json::Value::from(*value),
+            Field::Float(value) => serde_json::Value::from(*value),
+            Field::Text(value) => serde_json::Value::from(value.as_str()),
+            Field::Time(value) => serde_json::Value::from(value.to_rfc3339()),
+            Field::List(values) => serde_json::Value::from(values.clone()),
+            Field::Bool(value) => serde_json::Value::from(*value),
+            Field::Null => serde_json::Value::Null,
+        };
+        object.insert(name.to_string(), value);
+    }
+
+    serde_json::Value::Object(object)
+}
+
+// JSON documents are printed on a single line, to be easily read by scripts
+#[cfg(feature = "serde")]
+fn print_json(record: &Record) {
+    println!("{}", json_value(record));
+}
+
+#[cfg(feature = "serde")]
+fn print_json_list(records: &[Record]) {
+    println!("{}", json_list(records));
+}
+
+#[cfg(feature = "serde")]
+fn print_json_report(rows: &[Record], total: &Record) {
+    let mut object = serde_json::Map::new();
+    object.insert("rows".to_string(), json_list(rows));
+    object.insert("total".to_string(), json_value(total));
+    println!("{}", serde_json::Value::Object(object));
+}
+
+#[cfg(feature = "serde")]
+fn json_list(records: &[Record]) -> serde_json::Value {
+    serde_json::Value::Array(records.iter().map(json_value).collect())
+}
+
+// Cannot happen, as JSON output is refused when parsing the arguments
+#[cfg(not(feature = "serde"))]
+fn print_json(_record: &Record) {}
+
+#[cfg(not(feature = "serde"))]
+fn print_json_list(_records: &[Record]) {}
+
+#[cfg(not(feature = "serde"))]
+fn print_json_report(_rows: &[Record], _total: &Record) {}
+
+// A header with the field names, then one line for each record
+fn print_tsv(records: &[&Record], total: Option<&Record>) {
+    let first = match records.first().copied().or(total) {
+        Some(first) => first,
+        None => return,
+    };
+    println!("{}", first.iter().map(|(name, _)| *name).collect::<Vec<_>>().join("\t"));
+
+    for record in records.iter().copied().chain(total) {
+        let values: Vec<String> = record.iter().map(|(_, field)| tsv_value(field)).collect();
+        println!("{}", values.join("\t"));
+    }
+}
+
+fn tsv_value(field: &Field) -> String {
+    let value = match field {
+        Field::Int(value) => value.to_string(),
+        Field::Float(value) => value.to_string(),
+        Field::Text(value) => value.clone(),
+        Field::Time(value) => value.to_rfc3339(),
+        Field::List(values) => values.join(","),
+        Field::Bool(value) => value.to_string(),
+        Field::Null => String::new(),
+    };
+
+    value.replace(['\t', '\n'], " ")
+}
@@ -0,0 +1,172 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::errors::{Error, ValueError};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+static DATA_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+static PROFILE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Use the given directory instead of `TIMERS_DIR` or the default data location.
+pub fn set_data_dir(path: PathBuf) {
+    *DATA_DIR.lock().unwrap() = Some(path);
+}
+
+/// Use the given profile instead of the one selected with `switch_profile`.
+pub fn set_profile(name: &str) {
+    *PROFILE.lock().unwrap() = Some(name.to_string());
+}
+
+pub fn default_data_dir() -> PathBuf {
+    let mut path = dirs::data_dir().unwrap();
+    path.push("timers_time_logs");
+    path
+}
+
+// The data directory holds the tasks of the default profile,
+// named profiles are kept in the profiles subdirectory
+pub fn data_dir() -> PathBuf {
+    if let Some(path) = DATA_DIR.lock().unwrap().clone() {
+        return path;
+    }
+
+    match std::env::var_os("TIMERS_DIR") {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => default_data_dir(),
+    }
+}
+
+fn profiles_dir() -> PathBuf {
+    let mut path = data_dir();
+    path.push("profiles");
+    path
+}
+
+fn active_profile_file() -> PathBuf {
+    let mut path = data_dir();
+    path.push(".profile");
+    path
+}
+
+fn profile_path(name: &str) -> PathBuf {
+    if name == DEFAULT_PROFILE {
+        return data_dir();
+    }
+
+    let mut path = profiles_dir();
+    path.push(name);
+    path
+}
+
+fn validate_profile_name(name: &str) -> Result<(), Error> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::Value(ValueError::new(
+            format!(
+                "'{}' is not a valid profile name, use only letters, digits, '-', '_' and '.'.",
+                name
+            )
+            .as_str(),
+        )))
+    }
+}
+
+pub fn current_profile() -> Result<String, Error> {
+    if let Some(name) = PROFILE.lock().unwrap().clone() {
+        return Ok(name);
+    }
+
+    let path = active_profile_file();
+    if !path.exists() {
+        return Ok(DEFAULT_PROFILE.to_string());
+    }
+
+    let name = std::fs::read_to_string(path)?.trim().to_string();
+    if name.is_empty() {
+        Ok(DEFAULT_PROFILE.to_string())
+    } else {
+        Ok(name)
+    }
+}
+
+/// Directory containing the task files of the current profile.
+pub fn profile_data_dir() -> Result<PathBuf, Error> {
+    let name = current_profile()?;
+    validate_profile_name(&name)?;
+
+    let path = profile_path(&name);
+    if name != DEFAULT_PROFILE && !path.exists() {
+        return Err(Error::Value(ValueError::new(
+            format!(
+                "Profile '{}' does not exist, create it with 'timers profiles create {}'.",
+                name, name
+            )
+            .as_str(),
+        )));
+    }
+
+    Ok(path)
+}
+
+pub fn list_profiles() -> Result<Vec<String>, Error> {
+    let mut profiles = vec![DEFAULT_PROFILE.to_string()];
+
+    let path = profiles_dir();
+    if path.exists() {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.path().is_dir() {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        names.sort();
+        profiles.append(&mut names);
+    }
+
+    Ok(profiles)
+}
+
+pub fn create_profile(name: &str) -> Result<(), Error> {
+    validate_profile_name(name)?;
+
+    let path = profile_path(name);
+    if name == DEFAULT_PROFILE || path.exists() {
+        return Err(Error::Value(ValueError::new(
+            format!("Profile '{}' already exists.", name).as_str(),
+        )));
+    }
+
+    std::fs::create_dir_all(path)?;
+    Ok(())
+}
+
+/// Make the given profile the one used when no profile is specified.
+pub fn switch_profile(name: &str) -> Result<(), Error> {
+    validate_profile_name(name)?;
+
+    if name != DEFAULT_PROFILE && !profile_path(name).exists() {
+        return Err(Error::Value(ValueError::new(
+            format!("Profile '{}' does not exist.", name).as_str(),
+        )));
+    }
+
+    std::fs::create_dir_all(data_dir())?;
+    if name == DEFAULT_PROFILE {
+        if active_profile_file().exists() {
+            std::fs::remove_file(active_profile_file())?;
+        }
+    } else {
+        std::fs::write(active_profile_file(), format!("{}\n", name))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use colored::*;
+
+use crate::output;
+
+pub fn profiles_command(matches: &clap::ArgMatches) {
+    match matches.subcommand() {
+        ("create", Some(submatches)) => {
+            let name = submatches.value_of("NAME").unwrap();
+            match timers::create_profile(name) {
+                Ok(()) => print_profile_change(&format!("Created profile '{}'.", name), name),
+                Err(err) => output::fail_with("Error creating profile", &err),
+            }
+        }
+        ("switch", Some(submatches)) => {
+            let name = submatches.value_of("NAME").unwrap();
+            match timers::switch_profile(name) {
+                Ok(()) => print_profile_change(&format!("Switched to profile '{}'.", name), name),
+                Err(err) => output::fail_with("Error switching profile", &err),
+            }
+        }
+        _ => print_profiles(),
+    }
+}
+
+fn print_profile_change(message: &str, name: &str) {
+    if output::is_text() {
+        println!("{}", message);
+    } else {
+        output::print_record(&vec![("profile", output::Field::text(name))]);
+    }
+}
+
+fn print_profiles() {
+    let current = match timers::current_profile() {
+        Ok(current) => current,
+        Err(err) => return output::fail_with("Error finding current profile", &err),
+    };
+
+    match timers::list_profiles() {
+        Ok(profiles) if !output::is_text() => {
+            let records: Vec<output::Record> = profiles
+                .iter()
+                .map(|profile| {
+                    vec![
+                        ("name", output::Field::text(profile)),
+                        ("current", output::Field::Bool(*profile == current)),
+                    ]
+                })
+                .collect();
+            output::print_records(&records);
+        }
+        Ok(profiles) => {
+            for profile in profiles {
+                if profile == current {
+                    println!("* {}", profile.yellow().bold());
+                } else {
+                    println!("  {}", profile);
+                }
+            }
+        }
+        Err(err) => output::fail_with("Error listing profiles", &err),
+    }
+}
+
+pub fn migrate_command(matches: &clap::ArgMatches) {
+    let to = match timers::StorageKind::parse(matches.value_of("to").unwrap()) {
+        Ok(to) => to,
+        Err(err) => return output::usage_error(&err.to_string()),
+    };
+
+    match timers::migrate(to) {
+        Ok(count) if output::is_text() => println!("Converted {} tasks to {} storage.", count, to),
+        Ok(count) => output::print_record(&vec![
+            ("storage", output::Field::Text(to.to_string())),
+            ("tasks", output::Field::count(count)),
+        ]),
+        Err(err) => output::fail_with("Error converting tasks", &err),
+    }
+}
@@ -0,0 +1,65 @@
+use crate::errors::{Error, ValueError};
+
+/// A project tasks can belong to, optionally grouped under a client.
+/// Written as `client/project`, or just `project` when there is no client.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct Project {
+    pub client: Option<String>,
+    pub name: String,
+}
+
+impl Project {
+    pub fn parse(raw: &str) -> Result<Project, Error> {
+        let invalid = || {
+            Error::Value(ValueError::new(
+                format!("'{}' is not a valid project, use 'client/project' or 'project'.", raw)
+                    .as_str(),
+            ))
+        };
+
+        let parts: Vec<&str> = raw.trim().split('/').map(|part| part.trim()).collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(invalid());
+        }
+
+        match parts.as_slice() {
+            [name] => Ok(Project {
+                client: None,
+                name: name.to_string(),
+            }),
+            [client, name] => Ok(Project {
+                client: Some(client.to_string()),
+                name: name.to_string(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for Project {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.client {
+            Some(client) => write!(f, "{}/{}", client, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl From<Project> for String {
+    fn from(project: Project) -> String {
+        project.to_string()
+    }
+}
+
+impl std::convert::TryFrom<String> for Project {
+    type Error = Error;
+
+    fn try_from(raw: String) -> Result<Project, Error> {
+        Project::parse(&raw)
+    }
+}
@@ -3,20 +3,41 @@ use std::io::prelude::*;
 use std::ops::Add;
 
 use crate::errors::{Error, ValueError};
+use crate::project::Project;
+use crate::index::TaskIndex;
+use crate::storage::{missing_task, Storage, StorageLock, TaskSummary};
 
-#[derive(Debug, Copy, Clone)]
+/// Name of the lock file of plain text repositories.
+pub(crate) const LOCK_FILE: &str = ".lock";
+
+static WARNED: std::sync::Mutex<Vec<std::path::PathBuf>> = std::sync::Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Log {
     pub start: chrono::DateTime<chrono::Utc>,
     pub end: Option<chrono::DateTime<chrono::Utc>>,
+    /// What the time was spent on, as "fixed ticket 4411".
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub note: Option<String>,
 }
 
 impl Log {
+    pub fn new(
+        start: chrono::DateTime<chrono::Utc>,
+        end: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Log {
+        Log { start, end, note: None }
+    }
+
+    /// The duration up to the system time if the log is open, see `duration_at`.
     pub fn duration(&self) -> chrono::Duration {
-        if let Some(end) = self.end {
-            end.signed_duration_since(self.start)
-        } else {
-            chrono::Utc::now().signed_duration_since(self.start)
-        }
+        self.duration_at(chrono::Utc::now())
+    }
+
+    /// The duration up to `now` if the log is open.
+    pub fn duration_at(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::Duration {
+        self.end_at(now).signed_duration_since(self.start)
     }
 
     pub fn duration_between(
@@ -24,43 +45,88 @@ impl Log {
         start: chrono::DateTime<chrono::Utc>,
         end: chrono::DateTime<chrono::Utc>,
     ) -> chrono::Duration {
-        let task_end = self.end();
-
-        if start.le(&self.start) && end.ge(&task_end) {
-            self.duration()
-        } else if start.gt(&self.start) && end.ge(&task_end) {
-            let duration = task_end.signed_duration_since(start);
-            if duration.num_seconds() >= 0 {
-                duration
-            } else {
-                chrono::Duration::seconds(0)
-            }
-        } else if start.le(&self.start) && end.lt(&task_end) {
-            let duration = end.signed_duration_since(self.start);
-            if duration.num_seconds() >= 0 {
-                duration
-            } else {
-                chrono::Duration::seconds(0)
-            }
-        } else {
-            end.signed_duration_since(start)
-        }
+        self.duration_between_at(start, end, chrono::Utc::now())
     }
 
+    /// The part of the duration between start and end, up to `now` if the log is open.
+    pub fn duration_between_at(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> chrono::Duration {
+        let duration = self.end_at(now).min(end).signed_duration_since(self.start.max(start));
+        duration.max(chrono::Duration::seconds(0))
+    }
+
+    /// The end, or the system time if the log is open, see `end_at`.
     pub fn end(&self) -> chrono::DateTime<chrono::Utc> {
-        if self.end.is_none() {
-            chrono::Utc::now()
-        } else {
-            self.end.unwrap()
+        self.end_at(chrono::Utc::now())
+    }
+
+    /// The end, or `now` if the log is open.
+    pub fn end_at(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::DateTime<chrono::Utc> {
+        self.end.unwrap_or(now)
+    }
+
+    /// Whether the logs share some time. An open log is still running,
+    /// so it overlaps any log ending after its start, whatever the time.
+    pub fn overlaps(&self, other: &Log) -> bool {
+        other.end.is_none_or(|end| self.start < end) && self.end.is_none_or(|end| other.start < end)
+    }
+
+    // Parts of this log not covered by any of the others
+    pub fn subtract(&self, others: &[Log]) -> Vec<Log> {
+        let mut parts = vec![self.clone()];
+        for other in others {
+            let mut remaining = Vec::new();
+            for part in parts {
+                if !part.overlaps(other) {
+                    remaining.push(part);
+                    continue;
+                }
+
+                if part.start < other.start {
+                    remaining.push(Log {
+                        start: part.start,
+                        end: Some(other.start),
+                        note: part.note.clone(),
+                    });
+                }
+                let after = other.end.filter(|end| part.end.is_none_or(|part_end| *end < part_end));
+                if let Some(other_end) = after {
+                    remaining.push(Log {
+                        start: other_end,
+                        end: part.end,
+                        note: part.note.clone(),
+                    });
+                }
+            }
+            parts = remaining;
         }
+
+        parts
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Task {
     pub id: u32,
+    // Location in the repository, not part of the serialized task
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub path: std::path::PathBuf,
     pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub project: Option<Project>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tags: Vec<String>,
+    /// Archived tasks are hidden from `timers tasks`, but still counted in reports.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub archived: bool,
+    /// The logs of the break task are breaks, which are not counted as work.
+    #[cfg_attr(feature = "serde", serde(default, rename = "break"))]
+    pub is_break: bool,
     pub logs: Vec<Log>,
     pub logging: bool,
 }
@@ -72,10 +138,16 @@ pub enum TaskStatus {
 }
 
 impl Task {
+    /// The time logged, up to the system time if logging, see `duration_at`.
     pub fn duration(&self) -> chrono::Duration {
+        self.duration_at(chrono::Utc::now())
+    }
+
+    /// The time logged, up to `now` if logging.
+    pub fn duration_at(&self, now: chrono::DateTime<chrono::Utc>) -> chrono::Duration {
         let mut duration = chrono::Duration::seconds(0);
         for log in self.logs.iter() {
-            duration = duration + log.duration();
+            duration += log.duration_at(now);
         }
 
         duration
@@ -85,11 +157,21 @@ impl Task {
         &self,
         start: chrono::DateTime<chrono::Utc>,
         end: chrono::DateTime<chrono::Utc>,
+    ) -> chrono::Duration {
+        self.duration_between_at(start, end, chrono::Utc::now())
+    }
+
+    /// The time logged between start and end, up to `now` if logging.
+    pub fn duration_between_at(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+        end: chrono::DateTime<chrono::Utc>,
+        now: chrono::DateTime<chrono::Utc>,
     ) -> chrono::Duration {
         let mut total = chrono::Duration::seconds(0);
 
         for log in self.logs.iter() {
-            total = total.add(log.duration_between(start, end));
+            total = total.add(log.duration_between_at(start, end, now));
         }
 
         total
@@ -107,10 +189,23 @@ impl Task {
 
     pub fn status_text(&self) -> &str {
         match self.status() {
+            TaskStatus::Logging() if self.is_break => "on break",
             TaskStatus::Logging() => "logging",
             TaskStatus::Stopped() => "stopped",
         }
     }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn add_tags(&mut self, tags: &[String]) {
+        for tag in tags {
+            if !self.has_tag(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -120,170 +215,413 @@ pub struct Repo {
 
 impl Repo {
     fn read_task(path: std::path::PathBuf) -> Result<Task, Error> {
-        let file = std::fs::File::open(&path)?;
-        let mut reader = std::io::BufReader::new(file);
+        let content = std::fs::read_to_string(&path)?;
+        Repo::parse_task(path, &content)
+    }
 
-        let mut id_str = String::new();
-        reader.read_line(&mut id_str)?;
-        let id = id_str
-            .trim()
-            .parse::<u32>()
-            .expect("Unexpected or corrupt id value in task file");
+    /// Parses the content of a task file. Empty lines and lines
+    /// starting with # are ignored, except for the task name.
+    pub fn parse_task(path: std::path::PathBuf, content: &str) -> Result<Task, Error> {
+        let (task, mut errors) = Repo::parse_task_lenient(path, content)?;
+        if errors.is_empty() {
+            Ok(task)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
 
-        let mut name = String::new();
-        reader.read_line(&mut name)?;
+    /// Parses a task file skipping malformed lines, which are returned
+    /// as errors. Fails only if the task ID or name cannot be read.
+    pub fn parse_task_lenient(
+        path: std::path::PathBuf,
+        content: &str,
+    ) -> Result<(Task, Vec<Error>), Error> {
+        // Line numbers count from the task ID, so that they do not
+        // change when comments are added on top
+        let mut lines = content
+            .lines()
+            .skip_while(|line| line.starts_with('#'))
+            .enumerate()
+            .map(|(i, line)| (i + 1, line));
+
+        let (id_line, id_str) = lines.next().unwrap_or((1, ""));
+        let id = id_str.trim().parse::<u32>().map_err(|_| {
+            corrupt(&path, id_line, format!("invalid task ID '{}'", id_str.trim()))
+        })?;
+
+        let name = match lines.next() {
+            Some((_, name)) if !name.trim().is_empty() => name.trim().to_string(),
+            Some((name_line, _)) => return Err(corrupt(&path, name_line, "empty task name".to_string())),
+            None => return Err(corrupt(&path, id_line + 1, "missing task name".to_string())),
+        };
 
+        let mut errors = Vec::new();
+        let mut project = None;
+        let mut tags = Vec::new();
+        let mut archived = false;
+        let mut is_break = false;
         let mut logs = Vec::new();
         let mut logging = false;
-        loop {
-            let mut line = String::new();
-            let num_bytes_read = reader.read_line(&mut line)?;
-            if num_bytes_read == 0 {
-                break;
+        for (line_number, line) in lines {
+            // Logs start with a date, other lines are "key: value" task fields
+            if !line.starts_with(|c: char| c.is_ascii_digit()) {
+                if let Some(value) = line.strip_prefix("tags:") {
+                    tags = value.split_whitespace().map(|tag| tag.to_string()).collect();
+                } else if let Some(value) = line.strip_prefix("archived:") {
+                    match parse_flag(value) {
+                        Some(flag) => archived = flag,
+                        None => errors.push(corrupt(
+                            &path,
+                            line_number,
+                            format!("invalid archived value '{}'", value.trim()),
+                        )),
+                    }
+                } else if let Some(value) = line.strip_prefix("break:") {
+                    match parse_flag(value) {
+                        Some(flag) => is_break = flag,
+                        None => errors.push(corrupt(
+                            &path,
+                            line_number,
+                            format!("invalid break value '{}'", value.trim()),
+                        )),
+                    }
+                } else if let Some(value) = line.strip_prefix("project:") {
+                    match Project::parse(value) {
+                        Ok(parsed) => project = Some(parsed),
+                        Err(_) => errors.push(corrupt(
+                            &path,
+                            line_number,
+                            format!("invalid project '{}'", value.trim()),
+                        )),
+                    }
+                } else if !line.trim().is_empty() && !line.starts_with('#') {
+                    errors.push(corrupt(&path, line_number, format!("unexpected line '{}'", line)));
+                }
+                continue;
             }
 
-            let split: Vec<&str> = line.split(" ").collect();
-            let start = split[0].trim();
-            let end = split[1].trim();
-            if end.len() == 0 {
-                logging = true;
-            }
+            // The note, if any, follows the times after a #
+            let (times, note) = match line.split_once(" #") {
+                Some((times, note)) => (times, Some(note.trim().to_string())),
+                None => (line, None),
+            };
+            let mut split = times.split_whitespace();
+            let raw_start = split.next().unwrap_or("");
+            let raw_end = split.next().unwrap_or("");
+
+            let start = match parse_log_time(raw_start) {
+                Some(start) => start,
+                None => {
+                    errors.push(corrupt(&path, line_number, format!("invalid start time '{}'", raw_start)));
+                    continue;
+                }
+            };
+            let end = if raw_end.is_empty() {
+                None
+            } else {
+                match parse_log_time(raw_end) {
+                    Some(end) => Some(end),
+                    None => {
+                        errors.push(corrupt(&path, line_number, format!("invalid end time '{}'", raw_end)));
+                        continue;
+                    }
+                }
+            };
 
+            logging = logging || end.is_none();
             logs.push(Log {
-                start: chrono::DateTime::parse_from_rfc3339(start)
-                    .expect("Unexpected or corrupt start date value in task file")
-                    .with_timezone(&chrono::Utc),
-                end: if logging {
-                    None
-                } else {
-                    Some(
-                        chrono::DateTime::parse_from_rfc3339(end)
-                            .expect("Unexpected or corrupt end date value in task file")
-                            .with_timezone(&chrono::Utc),
-                    )
-                },
-            })
+                start,
+                end,
+                note: note.filter(|note| !note.is_empty()),
+            });
         }
 
-        Ok(Task {
+        let task = Task {
             id,
             path,
-            name: name.trim().to_string(),
+            name,
+            project,
+            tags,
+            archived,
+            is_break,
             logs,
             logging,
-        })
+        };
+
+        Ok((task, errors))
     }
 
-    fn write_task(task: &Task) -> Result<(), Error> {
-        let mut file = std::fs::File::create(&task.path)?;
+    /// Formats the task in the plain text format of the task files.
+    pub fn format_task(task: &Task) -> String {
+        let mut content = format!("{}\n{}\n", task.id, task.name);
 
-        write!(file, "{}\n{}\n", task.id, task.name)?;
+        if let Some(project) = &task.project {
+            content.push_str(&format!("project: {}\n", project));
+        }
+
+        if !task.tags.is_empty() {
+            content.push_str(&format!("tags: {}\n", task.tags.join(" ")));
+        }
+
+        if task.archived {
+            content.push_str("archived: true\n");
+        }
+
+        if task.is_break {
+            content.push_str("break: true\n");
+        }
 
         for log in task.logs.iter() {
-            write!(file, "{} ", log.start.to_rfc3339())?;
+            content.push_str(&format!("{} ", log.start.to_rfc3339()));
 
             if let Some(end) = log.end {
-                write!(file, "{}\n", end.to_rfc3339())?;
+                content.push_str(&end.to_rfc3339());
+            }
+            if let Some(note) = &log.note {
+                content.push_str(&format!(" # {}", note.replace(['\r', '\n'], " ")));
             }
+            content.push('\n');
         }
 
-        Ok(())
+        content
     }
 
-    pub fn list_tasks(&self) -> Result<HashMap<u32, Task>, Error> {
-        let paths = std::fs::read_dir(&self.path)?;
+    // The task is written to a temporary file which then replaces the
+    // task file, so that a crash never leaves it half written. Its ID is
+    // recorded, as tasks may also be written here for the first time.
+    fn write_task(&self, task: &Task) -> Result<(), Error> {
+        let mut temp_path = self.path.clone();
+        temp_path.push(format!(".{}.{}.tmp", task.id, std::process::id()));
+
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(Repo::format_task(task).as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, self.task_path(task.id))?;
+
+        // Make the rename itself durable, not possible on all platforms
+        if let Ok(dir) = std::fs::File::open(&self.path) {
+            let _ = dir.sync_all();
+        }
 
-        let mut tasks = HashMap::new();
-        for path in paths {
-            let path = path.unwrap().path();
-            let task = Repo::read_task(path)?;
-            tasks.insert(task.id, task);
+        let mut index = TaskIndex::load(&self.path);
+        index.update(task.id, &self.task_path(task.id), task);
+        let _ = index.save();
+
+        self.record_id(task.id)
+    }
+
+    // Paths of the task files, which are named after the task id. Anything
+    // else (such as the profiles directory) is not a task
+    pub fn task_files(&self) -> Result<Vec<(u32, std::path::PathBuf)>, Error> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let path = entry?.path();
+
+            let file_id = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse::<u32>().ok());
+            if let Some(file_id) = file_id {
+                if path.is_file() {
+                    files.push((file_id, path));
+                }
+            }
         }
 
-        Ok(tasks)
+        files.sort();
+        Ok(files)
     }
 
-    pub fn get_task(&self, id: u32) -> Result<Task, Error> {
+    fn last_id_path(&self) -> std::path::PathBuf {
         let mut path = self.path.clone();
-        path.push(id.to_string());
+        path.push(".last_id");
+        path
+    }
 
-        Ok(Repo::read_task(path)?)
+    // The highest ID ever given to a task, missing in older repositories
+    fn last_id(&self) -> u32 {
+        std::fs::read_to_string(self.last_id_path())
+            .ok()
+            .and_then(|content| content.trim().parse().ok())
+            .unwrap_or(0)
     }
 
-    pub fn create_task(&self, name: &str) -> Result<Task, Error> {
-        let id = self.next_id()?;
+    pub(crate) fn record_id(&self, id: u32) -> Result<(), Error> {
+        if id > self.last_id() {
+            std::fs::write(self.last_id_path(), format!("{}\n", id))?;
+        }
+        Ok(())
+    }
 
+    pub fn task_path(&self, id: u32) -> std::path::PathBuf {
         let mut path = self.path.clone();
         path.push(id.to_string());
+        path
+    }
+}
+
+impl Storage for Repo {
+    fn list_tasks(&self) -> Result<HashMap<u32, Task>, Error> {
+        let files = self.task_files()?;
+        let mut index = TaskIndex::load(&self.path);
+
+        let mut tasks = HashMap::new();
+        for (file_id, path) in &files {
+            if let Some(task) = read_task_or_warn(path) {
+                if index.get(*file_id, path).is_none() {
+                    index.update(*file_id, path, &task);
+                }
+                tasks.insert(task.id, task);
+            }
+        }
+
+        index.retain(&files.iter().map(|(id, _)| *id).collect::<Vec<_>>());
+        let _ = index.save();
+
+        Ok(tasks)
+    }
+
+    // Only the files changed since they were last indexed are read
+    fn list_summaries(&self) -> Result<Vec<TaskSummary>, Error> {
+        let files = self.task_files()?;
+        let mut index = TaskIndex::load(&self.path);
+
+        let mut summaries = Vec::new();
+        for (file_id, path) in &files {
+            if let Some(summary) = index.get(*file_id, path) {
+                summaries.push(summary);
+            } else if index.is_unreadable(*file_id, path) {
+                let warning = format!("Warning: skipping unreadable task file {}.", path.display());
+                warn_once(path, &warning);
+            } else if let Some(task) = read_task_or_warn(path) {
+                index.update(*file_id, path, &task);
+                summaries.push(TaskSummary::of(&task));
+            } else {
+                index.update_unreadable(*file_id, path);
+            }
+        }
+
+        index.retain(&files.iter().map(|(id, _)| *id).collect::<Vec<_>>());
+        let _ = index.save();
+
+        Ok(summaries)
+    }
+
+    fn get_task(&self, id: u32) -> Result<Task, Error> {
+        let path = self.task_path(id);
+        if !path.exists() {
+            return Err(missing_task(id));
+        }
+
+        Repo::read_task(path)
+    }
+
+    fn create_task_with_id(&self, id: u32, name: &str) -> Result<Task, Error> {
+        if self.task_path(id).exists() {
+            return Err(Error::Value(ValueError::new(
+                format!("Task @{} already exists.", id).as_str(),
+            )));
+        }
 
         let task = Task {
             id,
-            path,
+            path: self.task_path(id),
             name: name.to_string(),
+            project: None,
+            tags: Vec::new(),
+            archived: false,
+            is_break: false,
             logs: Vec::new(),
             logging: false,
         };
 
-        Repo::write_task(&task)?;
+        self.write_task(&task)?;
 
         Ok(task)
     }
 
-    fn next_id(&self) -> Result<u32, Error> {
-        let tasks = self.list_tasks()?;
+    fn replace_task(&self, task: &Task) -> Result<(), Error> {
+        self.write_task(task)
+    }
 
-        let mut max_id = 0u32;
-        for id in tasks.keys() {
-            if id > &max_id {
-                max_id = *id;
-            }
+    fn delete_task(&self, id: u32) -> Result<(), Error> {
+        let path = self.task_path(id);
+        if !path.exists() {
+            return Err(missing_task(id));
         }
 
-        Ok(max_id + 1)
+        std::fs::remove_file(path)?;
+        Ok(())
     }
 
-    pub fn log_task(
-        &self,
-        task: &mut Task,
-        time: chrono::DateTime<chrono::Utc>,
-    ) -> Result<(), Error> {
-        task.logging = true;
-        task.logs.push(Log {
-            start: time,
-            end: None,
-        });
-
-        Repo::write_task(&task)?;
+    // Based on the file names, so that the IDs of unreadable tasks are not
+    // reused, and on the last ID given, for the tasks deleted since then
+    fn next_id(&self) -> Result<u32, Error> {
+        let max_id = self.task_files()?.iter().map(|(id, _)| *id).max().unwrap_or(0);
+        Ok(max_id.max(self.last_id()) + 1)
+    }
 
-        Ok(())
+    fn lock(&self) -> Result<StorageLock, Error> {
+        let mut path = self.path.clone();
+        path.push(LOCK_FILE);
+        StorageLock::file(&path)
     }
 
-    pub fn stop_task(
-        &self,
-        task: &mut Task,
-        time: chrono::DateTime<chrono::Utc>,
-    ) -> Result<(), Error> {
-        task.logging = false;
-
-        match task.logs.last_mut() {
-            Some(log) => {
-                if log.end.is_some() {
-                    return Err(Error::Value(ValueError::new(
-                        "Task was not started, cannot stop logging.",
-                    )));
-                }
+    // The file as it is, so that corrupt tasks can be edited too
+    fn task_content(&self, id: u32) -> Result<String, Error> {
+        let path = self.task_path(id);
+        if !path.exists() {
+            return Err(missing_task(id));
+        }
 
-                log.end = Some(time)
-            }
-            None => {
-                return Err(Error::Value(ValueError::new(
-                    "Task was not started, cannot stop logging.",
-                )))
-            }
+        Ok(std::fs::read_to_string(path)?)
+    }
+}
+
+// Files which cannot be read are skipped with a warning,
+// so that a single bad file does not break every command
+fn read_task_or_warn(path: &std::path::Path) -> Option<Task> {
+    match Repo::read_task(path.to_path_buf()) {
+        Ok(task) => Some(task),
+        Err(err @ Error::Corrupt { .. }) => {
+            warn_once(path, &format!("Warning: skipping task. {}.", err));
+            None
+        }
+        Err(err) => {
+            warn_once(path, &format!("Warning: skipping task {}: {}", path.display(), err));
+            None
         }
+    }
+}
 
-        Repo::write_task(&task)?;
+// Tasks are listed several times by a single command,
+// which should only warn once about each file
+fn warn_once(path: &std::path::Path, warning: &str) {
+    let mut warned = WARNED.lock().unwrap();
+    if !warned.iter().any(|other| other == path) {
+        eprintln!("{} Run 'timers fsck' for details.", warning);
+        warned.push(path.to_path_buf());
+    }
+}
 
-        Ok(())
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_log_time(raw_time: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(raw_time)
+        .ok()
+        .map(|time| time.with_timezone(&chrono::Utc))
+}
+
+fn corrupt(path: &std::path::Path, line: usize, reason: String) -> Error {
+    Error::Corrupt {
+        path: path.to_path_buf(),
+        line,
+        reason,
     }
 }
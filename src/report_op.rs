@@ -1,80 +1,717 @@
-use std::ops::{Add, Sub};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::ops::Add;
 
-use chrono::{Datelike, Timelike};
+use chrono::{Datelike, TimeZone};
 use colored::*;
+use itertools::Itertools;
 
-use timers;
+use crate::output;
+use crate::util::{
+    config, day_start, parse_int, parse_offset, parse_time, parse_week, task_filter,
+    week_start_date,
+};
+
+// A row of a report, covering the time between start and end
+struct Period {
+    label: ColoredString,
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+}
 
 pub fn report_days_command(matches: &clap::ArgMatches) {
-    if !matches.is_present("plain") {
-        println!("{:<12} {:<14} {}", "DAY", "TIME LOGGED", "TASKS");
-        println!("{}", "-".repeat(34));
-    }
-
-    let week_offset = chrono::Local::now().weekday().num_days_from_monday() as i64;
-    let week_start = chrono::Local::now()
-        .with_hour(0)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap()
-        .with_nanosecond(0)
-        .unwrap()
-        .sub(chrono::Duration::days(week_offset))
-        .with_timezone(&chrono::Utc);
-
-    for i in 0..7 {
-        let start = week_start.add(chrono::Duration::days(i));
-        let end = week_start.add(chrono::Duration::days(i + 1));
-
-        let local_start = start.with_timezone(&chrono::Local);
-        let tasks = timers::get_all_tasks_between(start, end).unwrap_or_else(|err| {
-            println!("Error retrieving tasks: {}", err);
-            std::process::exit(2);
+    let week_start = match matches.value_of("week") {
+        Some(raw_week) => match parse_week(raw_week) {
+            Some(week_start) => week_start,
+            None => return,
+        },
+        None => week_start_date(chrono::Local::now().date_naive()),
+    };
+
+    let periods = (0..7)
+        .map(|i| {
+            let date = week_start + chrono::Duration::days(i);
+            Period {
+                label: day_label(date, date.format("%A").to_string()),
+                start: day_start(date),
+                end: day_start(date + chrono::Duration::days(1)),
+            }
+        })
+        .collect();
+
+    print_periods("DAY", periods, matches);
+}
+
+pub fn report_weeks_command(matches: &clap::ArgMatches) {
+    let month_start = match matches.value_of("month") {
+        Some(raw_month) => match parse_month(raw_month) {
+            Some(month_start) => month_start,
+            None => return,
+        },
+        None => chrono::Local::now().date_naive().with_day(1).unwrap(),
+    };
+    // Cannot panic as parse_month already ensures the month has an end
+    let month_end = add_months(month_start, 1).unwrap();
+
+    // Weeks are clipped to the month, so that the total is the month total
+    let mut periods = Vec::new();
+    let mut start = month_start;
+    while start < month_end {
+        let end = (week_start_date(start) + chrono::Duration::weeks(1)).min(month_end);
+        periods.push(Period {
+            label: format!(
+                "W{:02} {}-{}",
+                start.iso_week().week(),
+                start.format("%d"),
+                (end - chrono::Duration::days(1)).format("%d"),
+            )
+            .normal(),
+            start: day_start(start),
+            end: day_start(end),
         });
+        start = end;
+    }
+
+    print_periods("WEEK", periods, matches);
+}
+
+pub fn report_months_command(matches: &clap::ArgMatches) {
+    let year = match matches.value_of("year") {
+        Some(raw_year) => match parse_year(raw_year) {
+            Some(year) => year,
+            None => return,
+        },
+        None => chrono::Local::now().year(),
+    };
+
+    let periods = (1..=12)
+        .map(|month| {
+            // Cannot panic as parse_year already ensures the year is in range
+            let month_start = chrono::NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+            Period {
+                label: month_start.format("%B").to_string().normal(),
+                start: day_start(month_start),
+                end: day_start(add_months(month_start, 1).unwrap()),
+            }
+        })
+        .collect();
+
+    print_periods("MONTH", periods, matches);
+}
+
+pub fn report_range_command(matches: &clap::ArgMatches) {
+    let (from, to) = match report_window(matches) {
+        Some(window) => window,
+        None => return,
+    };
+
+    print_periods("DAY", day_periods(from, to), matches);
+}
+
+pub fn report_tasks_command(matches: &clap::ArgMatches) {
+    let filter = match task_filter(matches) {
+        Some(filter) => filter,
+        None => return,
+    };
+
+    let (start, end) = match report_window(matches) {
+        Some(window) => window,
+        None => return,
+    };
+
+    let format = if matches.is_present("tot-hours") {
+        timers::format_duration_hours
+    } else {
+        timers::format_duration
+    };
+
+    // Tasks which were logged in the window, longest first
+    let tasks = tasks_between(start, end, &filter);
+    let tasks: Vec<(&timers::Task, chrono::Duration)> = tasks
+        .values()
+        .map(|task| (task, task.duration_between(start, end)))
+        .filter(|(_, duration)| duration.num_seconds() > 0)
+        .sorted_by_key(|(task, duration)| (-duration.num_seconds(), task.id))
+        .collect();
+
+    let total = tasks
+        .iter()
+        .fold(chrono::Duration::seconds(0), |total, (_, duration)| total.add(*duration));
+
+    if matches.value_of("by") == Some("day") {
+        print_tasks_by_day(&tasks, start, end, total, matches);
+        return;
+    }
+
+    if !output::is_text() {
+        let rows: Vec<output::Record> = tasks
+            .iter()
+            .map(|(task, duration)| {
+                vec![
+                    ("id", output::Field::Int(task.id as i64)),
+                    ("name", output::Field::text(&task.name)),
+                    ("duration_seconds", output::Field::seconds(*duration)),
+                    ("percentage", output::Field::Float(percentage(*duration, total))),
+                ]
+            })
+            .collect();
+        let total_record = vec![
+            ("id", output::Field::Null),
+            ("name", output::Field::text("Total")),
+            ("duration_seconds", output::Field::seconds(total)),
+            ("percentage", output::Field::Float(percentage(total, total))),
+        ];
+        return output::print_report(&rows, &total_record);
+    }
+
+    if !matches.is_present("plain") {
+        println!("{:<6} {:<36} {:<14} %", "ID", "TASK", "TIME LOGGED");
+        println!("{}", "-".repeat(65));
+    }
+
+    for (task, duration) in tasks.iter() {
+        println!(
+            "{:<6} {:<36} {:<14} {:.1}%",
+            format!("@{}", task.id),
+            task.name,
+            format(*duration),
+            percentage(*duration, total),
+        );
+    }
+
+    if !matches.is_present("plain") {
+        println!("{}", "-".repeat(65));
+        println!("{:<43} {:<14} {:.1}%", "Total", format(total), percentage(total, total));
+    }
+}
 
-        let duration = timers::get_total_duration(start, end).unwrap_or_else(|err| {
-            println!("Error computing duration: {}", err);
-            std::process::exit(2);
+// Task × day matrix, as asked by timesheets
+fn print_tasks_by_day(
+    tasks: &[(&timers::Task, chrono::Duration)],
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+    total: chrono::Duration,
+    matches: &clap::ArgMatches,
+) {
+    let format = if matches.is_present("tot-hours") {
+        timers::format_duration_hours
+    } else {
+        timers::format_duration
+    };
+    let format_cell = |duration: chrono::Duration| {
+        if duration.num_seconds() > 0 {
+            format(duration)
+        } else {
+            "-".to_string()
+        }
+    };
+
+    let days = day_periods(start, end);
+    let width = 10;
+
+    // One row for each task and day with time logged
+    if !output::is_text() {
+        let day_record = |id: Option<u32>, name: &str, day: Option<&Period>, duration| {
+            let date = day.map(|day| day.start.with_timezone(&chrono::Local).format("%Y-%m-%d"));
+            vec![
+                ("id", id.map_or(output::Field::Null, |id| output::Field::Int(id as i64))),
+                ("name", output::Field::text(name)),
+                ("date", output::Field::opt(date)),
+                ("duration_seconds", output::Field::seconds(duration)),
+            ]
+        };
+        let mut rows = Vec::new();
+        for (task, _) in tasks {
+            for day in days.iter() {
+                let duration = task.duration_between(day.start, day.end);
+                if duration.num_seconds() > 0 {
+                    rows.push(day_record(Some(task.id), &task.name, Some(day), duration));
+                }
+            }
+        }
+        return output::print_report(&rows, &day_record(None, "Total", None, total));
+    }
+
+    if !matches.is_present("plain") {
+        let mut header = format!("{:<6} {:<24}", "ID", "TASK");
+        for day in days.iter() {
+            let label = day.start.with_timezone(&chrono::Local).format("%a %d").to_string();
+            header.push_str(format!(" {:<width$}", label, width = width).as_str());
+        }
+        println!("{} TOTAL", header);
+        println!("{}", "-".repeat(31 + (days.len() + 1) * (width + 1)));
+    }
+
+    for (task, duration) in tasks {
+        let mut name = task.name.clone();
+        if name.chars().count() > 24 {
+            name = name.chars().take(23).collect::<String>() + "…";
+        }
+
+        let mut row = format!("{:<6} {:<24}", format!("@{}", task.id), name);
+        for day in days.iter() {
+            let cell = format_cell(task.duration_between(day.start, day.end));
+            row.push_str(format!(" {:<width$}", cell, width = width).as_str());
+        }
+        println!("{} {}", row, format(*duration));
+    }
+
+    if !matches.is_present("plain") {
+        println!("{}", "-".repeat(31 + (days.len() + 1) * (width + 1)));
+
+        let mut row = format!("{:<31}", "Total");
+        for day in days.iter() {
+            let day_total = tasks.iter().fold(chrono::Duration::seconds(0), |total, (task, _)| {
+                total.add(task.duration_between(day.start, day.end))
+            });
+            row.push_str(format!(" {:<width$}", format_cell(day_total), width = width).as_str());
+        }
+        println!("{} {}", row, format(total));
+    }
+}
+
+fn format_breaks(breaks: chrono::Duration) -> String {
+    if breaks.num_seconds() > 0 {
+        timers::format_duration(breaks)
+    } else {
+        "-".to_string()
+    }
+}
+
+fn percentage(duration: chrono::Duration, total: chrono::Duration) -> f64 {
+    if total.num_seconds() == 0 {
+        0.
+    } else {
+        duration.num_seconds() as f64 / total.num_seconds() as f64 * 100.
+    }
+}
+
+// Reads --from and --to, by default the current week
+fn report_window(
+    matches: &clap::ArgMatches,
+) -> Option<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+    let start = match matches.value_of("from") {
+        Some(from) => parse_time(from)?,
+        None => current_week_start(),
+    };
+    let end = match matches.value_of("to") {
+        Some(to) => parse_time(to)?,
+        None => start.add(chrono::Duration::weeks(1)),
+    };
+
+    if end <= start {
+        output::usage_error("The end of the range must be after its start.");
+        return None;
+    }
+
+    Some((start, end))
+}
+
+// One period for each day, the first and last ones are clipped to the range
+fn day_periods(
+    from: chrono::DateTime<chrono::Utc>,
+    to: chrono::DateTime<chrono::Utc>,
+) -> Vec<Period> {
+    let mut periods = Vec::new();
+    let mut date = from.with_timezone(&chrono::Local).date_naive();
+    loop {
+        let start = day_start(date).max(from);
+        let end = day_start(date + chrono::Duration::days(1)).min(to);
+        if start >= to {
+            break;
+        }
+
+        periods.push(Period {
+            label: day_label(date, date.format("%a %Y-%m-%d").to_string()),
+            start,
+            end,
         });
+        date += chrono::Duration::days(1);
+    }
+
+    periods
+}
+
+fn print_periods(header: &str, periods: Vec<Period>, matches: &clap::ArgMatches) {
+    let filter = match task_filter(matches) {
+        Some(filter) => filter,
+        None => return,
+    };
+
+    // The whole range goes last, for the total
+    let mut ranges: Vec<_> = periods.iter().map(|period| (period.start, period.end)).collect();
+    if let (Some(first), Some(last)) = (periods.first(), periods.last()) {
+        ranges.push((first.start, last.end));
+    }
+    let mut totals = match timers::get_period_totals(&ranges, &filter) {
+        Ok(totals) => totals,
+        Err(err) => return output::fail_with("Error retrieving tasks", &err),
+    };
+    let total = if periods.is_empty() { None } else { totals.pop() };
+
+    if !output::is_text() {
+        let period_record = |label: &str, period_total: &timers::PeriodTotal| {
+            vec![
+                ("label", output::Field::text(label)),
+                ("start", output::Field::Time(period_total.start)),
+                ("end", output::Field::Time(period_total.end)),
+                ("duration_seconds", output::Field::seconds(period_total.duration)),
+                ("break_seconds", output::Field::seconds(period_total.breaks)),
+                ("tasks", output::Field::count(period_total.tasks)),
+            ]
+        };
+        let rows: Vec<output::Record> = periods
+            .iter()
+            .zip(totals.iter())
+            .map(|(period, period_total)| period_record(&period.label, period_total))
+            .collect();
+        return match total {
+            Some(total) => output::print_report(&rows, &period_record("Total", &total)),
+            None => output::print_records(&rows),
+        };
+    }
+
+    let width = periods
+        .iter()
+        .map(|period| period.label.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(12);
+    let separator = "-".repeat(width + 37);
 
+    if !matches.is_present("plain") {
+        println!("{:<width$} {:<14} {:<14} TASKS", header, "TIME LOGGED", "BREAKS", width = width);
+        println!("{}", separator);
+    }
+
+    for (period, period_total) in periods.iter().zip(totals.iter()) {
         println!(
-            "{:<12} {:<14} {}",
-            if i < 5 {
-                local_start.format("%A").to_string().green()
-            } else {
-                local_start.format("%A").to_string().red()
-            },
-            timers::format_duration(duration),
-            tasks.len(),
+            "{:<width$} {:<14} {:<14} {}",
+            period.label,
+            timers::format_duration(period_total.duration),
+            format_breaks(period_total.breaks).dimmed(),
+            period_total.tasks,
+            width = width,
+        )
+    }
+
+    if let Some(total) = total.filter(|_| !matches.is_present("plain")) {
+        println!("{}", separator);
+
+        let format = if matches.is_present("tot-hours") {
+            timers::format_duration_hours
+        } else {
+            timers::format_duration
+        };
+        println!(
+            "{:<width$} {:<14} {:<14} {}",
+            "Total",
+            format(total.duration),
+            format(total.breaks),
+            total.tasks,
+            width = width,
         )
     }
+}
+
+pub fn report_projects_command(matches: &clap::ArgMatches) {
+    let filter = match task_filter(matches) {
+        Some(filter) => filter,
+        None => return,
+    };
+
+    let (start, end) = match report_window(matches) {
+        Some(window) => window,
+        None => return,
+    };
+
+    let tasks = tasks_between(start, end, &filter);
+    print_projects(&tasks, start, end, matches);
+}
+
+pub fn report_breaks_command(matches: &clap::ArgMatches) {
+    let (start, end) = match report_window(matches) {
+        Some(window) => window,
+        None => return,
+    };
+
+    // All work counts towards the minimum break, whatever its tags
+    let days = day_periods(start, end);
+    let ranges: Vec<_> = days.iter().map(|day| (day.start, day.end)).collect();
+    let totals = match timers::get_period_totals(&ranges, &timers::TaskFilter::default()) {
+        Ok(totals) => totals,
+        Err(err) => return output::fail_with("Error retrieving tasks", &err),
+    };
+
+    let minimum = config().minimum_break();
+    let required_after = config().break_required_after();
+    let required = |total: &timers::PeriodTotal| {
+        Some(minimum).filter(|_| minimum.num_seconds() > 0 && total.duration >= required_after)
+    };
+    let missing = |total: &timers::PeriodTotal| {
+        required(total)
+            .map(|required| required - total.breaks)
+            .filter(|missing| missing.num_seconds() > 0)
+    };
+    let short_days = totals.iter().filter(|total| missing(total).is_some()).count();
+    if short_days > 0 {
+        output::set_exit_code(output::EXIT_FAILURE);
+    }
+
+    if !output::is_text() {
+        let rows: Vec<output::Record> = days
+            .iter()
+            .zip(totals.iter())
+            .map(|(day, total)| {
+                vec![
+                    ("label", output::Field::text(&day.label)),
+                    ("start", output::Field::Time(total.start)),
+                    ("end", output::Field::Time(total.end)),
+                    ("duration_seconds", output::Field::seconds(total.duration)),
+                    ("break_seconds", output::Field::seconds(total.breaks)),
+                    (
+                        "required_seconds",
+                        required(total).map_or(output::Field::Null, output::Field::seconds),
+                    ),
+                    (
+                        "missing_seconds",
+                        missing(total).map_or(output::Field::Null, output::Field::seconds),
+                    ),
+                ]
+            })
+            .collect();
+        return output::print_records(&rows);
+    }
 
     if !matches.is_present("plain") {
-        println!("{}", "-".repeat(34));
+        println!("{:<16} {:<14} {:<14} MINIMUM BREAK", "DAY", "TIME LOGGED", "BREAKS");
+        println!("{}", "-".repeat(60));
+    }
 
-        let week_end = week_start.add(chrono::Duration::weeks(1));
+    for (day, total) in days.iter().zip(totals.iter()) {
+        let check = match (required(total), missing(total)) {
+            (_, Some(missing)) => format!("{} missing", timers::format_duration(missing)).red(),
+            (Some(_), None) => "ok".green(),
+            (None, None) => "-".normal(),
+        };
+        println!(
+            "{:<16} {:<14} {:<14} {}",
+            day.label,
+            timers::format_duration(total.duration),
+            format_breaks(total.breaks),
+            check,
+        );
+    }
 
-        let tasks = timers::get_all_tasks_between(week_start, week_end).unwrap_or_else(|err| {
-            println!("Error retrieving tasks: {}", err);
-            std::process::exit(2);
-        });
+    if short_days > 0 && !matches.is_present("plain") {
+        println!("{}", "-".repeat(60));
+        println!(
+            "{} {} with less than {} of break after {} of work",
+            short_days,
+            if short_days == 1 { "day" } else { "days" },
+            timers::format_duration(minimum),
+            timers::format_duration(required_after),
+        );
+    }
+}
 
-        let duration = timers::get_total_duration(week_start, week_end).unwrap_or_else(|err| {
-            println!("Error computing duration: {}", err);
-            std::process::exit(2);
-        });
+pub fn projects_command(matches: &clap::ArgMatches) {
+    let filter = match task_filter(matches) {
+        Some(filter) => filter,
+        None => return,
+    };
+
+    let tasks = match timers::get_all_tasks() {
+        Ok(tasks) => tasks,
+        Err(err) => return output::fail_with("Error retrieving tasks", &err),
+    };
+    let tasks = without_breaks(filter.apply(tasks));
+
+    let start = chrono::Utc.with_ymd_and_hms(1900, 1, 1, 0, 0, 0).unwrap();
+    let end = chrono::Utc.with_ymd_and_hms(2100, 1, 1, 0, 0, 0).unwrap();
+    print_projects(&tasks, start, end, matches);
+}
+
+fn print_projects(
+    tasks: &HashMap<u32, timers::Task>,
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+    matches: &clap::ArgMatches,
+) {
+    let format = if matches.is_present("tot-hours") {
+        timers::format_duration_hours
+    } else {
+        timers::format_duration
+    };
+
+    // Number of tasks and time logged for each project
+    let mut projects: BTreeMap<Option<timers::Project>, (usize, chrono::Duration)> = BTreeMap::new();
+    for task in tasks.values() {
+        let entry = projects
+            .entry(task.project.clone())
+            .or_insert((0, chrono::Duration::seconds(0)));
+        entry.0 += 1;
+        entry.1 = entry.1.add(task.duration_between(start, end));
+    }
+
+    let mut clients: BTreeMap<String, Vec<(String, usize, chrono::Duration)>> = BTreeMap::new();
+    for (project, (count, duration)) in projects.iter() {
+        if let Some(timers::Project { client: Some(client), .. }) = project {
+            clients
+                .entry(client.clone())
+                .or_default()
+                .push((project.as_ref().unwrap().to_string(), *count, *duration));
+        }
+    }
 
+    if !output::is_text() {
+        let project_record = |project: Option<&timers::Project>, count, duration| {
+            vec![
+                ("project", output::Field::opt(project)),
+                ("client", output::Field::opt(project.and_then(|project| project.client.as_ref()))),
+                ("tasks", output::Field::count(count)),
+                ("duration_seconds", output::Field::seconds(duration)),
+            ]
+        };
+        let rows: Vec<output::Record> = projects
+            .iter()
+            .map(|(project, (count, duration))| project_record(project.as_ref(), *count, *duration))
+            .collect();
+        let total = project_record(None, tasks.len(), total_duration(tasks, start, end));
+        return output::print_report(&rows, &total);
+    }
+
+    if !matches.is_present("plain") {
+        println!("{:<36} {:<6} TIME LOGGED", "PROJECT", "TASKS");
+        println!("{}", "-".repeat(56));
+    }
+
+    for (client, client_projects) in clients.iter() {
+        let count: usize = client_projects.iter().map(|(_, count, _)| count).sum();
+        let duration = client_projects
+            .iter()
+            .fold(chrono::Duration::seconds(0), |total, (_, _, duration)| total.add(*duration));
+        println!("{:<36} {:<6} {}", client.bold(), count, format(duration).bold());
+
+        for (project, count, duration) in client_projects {
+            println!("{:<36} {:<6} {}", format!("  {}", project), count, format(*duration));
+        }
+    }
+
+    for (project, (count, duration)) in projects.iter() {
+        if let Some(timers::Project { client: None, name }) = project {
+            println!("{:<36} {:<6} {}", name.bold(), count, format(*duration).bold());
+        }
+    }
+
+    if let Some((count, duration)) = projects.get(&None) {
+        println!("{:<36} {:<6} {}", "(no project)", count, format(*duration));
+    }
+
+    if !matches.is_present("plain") {
+        println!("{}", "-".repeat(56));
         println!(
-            "{:<12} {:<14} {}",
+            "{:<36} {:<6} {}",
             "Total",
-            if matches.is_present("tot-hours") {
-                timers::format_duration_hours(duration)
-            } else {
-                timers::format_duration(duration)
-            },
             tasks.len(),
-        )
+            format(total_duration(tasks, start, end)),
+        );
+    }
+}
+
+// Work days are green, the others red
+fn day_label(date: chrono::NaiveDate, label: String) -> ColoredString {
+    if config().is_work_day(date.weekday()) {
+        label.green()
+    } else {
+        label.red()
+    }
+}
+
+fn current_week_start() -> chrono::DateTime<chrono::Utc> {
+    day_start(week_start_date(chrono::Local::now().date_naive()))
+}
+
+fn add_months(date: chrono::NaiveDate, months: i32) -> Option<chrono::NaiveDate> {
+    let index = (date.year() * 12 + date.month0() as i32).checked_add(months)?;
+    chrono::NaiveDate::from_ymd_opt(index.div_euclid(12), index.rem_euclid(12) as u32 + 1, 1)
+}
+
+// The returned month also has a valid end, the start of the next one
+fn parse_month(raw_month: &str) -> Option<chrono::NaiveDate> {
+    let this_month = chrono::Local::now().date_naive().with_day(1).unwrap();
+    let month = match parse_offset(raw_month) {
+        Some(offset) => i32::try_from(offset)
+            .ok()
+            .and_then(|offset| add_months(this_month, offset)),
+        None => chrono::NaiveDate::parse_from_str(&format!("{}-01", raw_month), "%Y-%m-%d").ok(),
+    };
+
+    match month.filter(|month| add_months(*month, 1).is_some()) {
+        Some(month) => Some(month),
+        None => {
+            output::usage_error(&format!(
+                "Month '{}' not understood, use an offset such as -1 or YYYY-MM",
+                raw_month
+            ));
+            None
+        }
+    }
+}
+
+// The returned year has valid dates for all its months and the next year start
+fn parse_year(raw_year: &str) -> Option<i32> {
+    let this_year = chrono::Local::now().year();
+    let year = match parse_offset(raw_year) {
+        Some(offset) => i32::try_from(offset)
+            .ok()
+            .and_then(|offset| this_year.checked_add(offset)),
+        None => parse_int(raw_year).ok().and_then(|year| i32::try_from(year).ok()),
+    };
+
+    let valid = |year: &i32| {
+        chrono::NaiveDate::from_ymd_opt(*year, 1, 1).is_some()
+            && year
+                .checked_add(1)
+                .and_then(|next| chrono::NaiveDate::from_ymd_opt(next, 1, 1))
+                .is_some()
+    };
+    match year.filter(valid) {
+        Some(year) => Some(year),
+        None => {
+            output::usage_error(&format!(
+                "Year '{}' not understood, use an offset such as -1 or YYYY",
+                raw_year
+            ));
+            None
+        }
     }
 }
+
+fn tasks_between(
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+    filter: &timers::TaskFilter,
+) -> HashMap<u32, timers::Task> {
+    let tasks = timers::get_all_tasks_between(start, end).unwrap_or_else(|err| {
+        output::fail_with("Error retrieving tasks", &err);
+        std::process::exit(output::exit_code());
+    });
+
+    without_breaks(filter.apply(tasks))
+}
+
+// Breaks are not work, they are only shown by the period reports
+fn without_breaks(tasks: HashMap<u32, timers::Task>) -> HashMap<u32, timers::Task> {
+    tasks.into_iter().filter(|(_, task)| !task.is_break).collect()
+}
+
+fn total_duration(
+    tasks: &HashMap<u32, timers::Task>,
+    start: chrono::DateTime<chrono::Utc>,
+    end: chrono::DateTime<chrono::Utc>,
+) -> chrono::Duration {
+    tasks
+        .values()
+        .fold(chrono::Duration::seconds(0), |total, task| total.add(task.duration_between(start, end)))
+}
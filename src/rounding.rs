@@ -0,0 +1,56 @@
+use crate::errors::{Error, ValueError};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RoundingMode {
+    Nearest,
+    Up,
+    Down,
+}
+
+/// Rounds durations to a multiple of `increment`.
+#[derive(Debug, Copy, Clone)]
+pub struct Rounding {
+    pub mode: RoundingMode,
+    pub increment: chrono::Duration,
+}
+
+impl RoundingMode {
+    pub fn parse(raw: &str) -> Result<RoundingMode, Error> {
+        match raw {
+            "nearest" => Ok(RoundingMode::Nearest),
+            "up" => Ok(RoundingMode::Up),
+            "down" => Ok(RoundingMode::Down),
+            _ => Err(Error::Value(ValueError::new(
+                format!("'{}' is not a rounding mode, use nearest, up or down.", raw).as_str(),
+            ))),
+        }
+    }
+}
+
+impl Rounding {
+    pub fn new(mode: RoundingMode, increment_minutes: i64) -> Result<Rounding, Error> {
+        if increment_minutes <= 0 {
+            return Err(Error::Value(ValueError::new(
+                "The rounding increment must be positive.",
+            )));
+        }
+
+        Ok(Rounding {
+            mode,
+            increment: chrono::Duration::minutes(increment_minutes),
+        })
+    }
+
+    pub fn round(&self, duration: chrono::Duration) -> chrono::Duration {
+        let increment = self.increment.num_seconds();
+        let seconds = duration.num_seconds();
+
+        let increments = match self.mode {
+            RoundingMode::Down => seconds.div_euclid(increment),
+            RoundingMode::Up => (seconds + increment - 1).div_euclid(increment),
+            RoundingMode::Nearest => (seconds + increment / 2).div_euclid(increment),
+        };
+
+        chrono::Duration::seconds(increments * increment)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::storage::TaskSummary;
+
+/// How well a task name matches what was searched, from best to worst.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    Exact,
+    IgnoreCase,
+    Prefix,
+    Words,
+    Fuzzy,
+}
+
+impl MatchKind {
+    /// Whether the name is the one searched, only differing by case.
+    pub fn is_exact(self) -> bool {
+        self <= MatchKind::IgnoreCase
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskMatch {
+    pub task: TaskSummary,
+    pub kind: MatchKind,
+}
+
+/// The tasks whose name matches the query, best matches first and,
+/// for matches as good, the most recently logged first.
+pub fn match_tasks(query: &str, tasks: Vec<TaskSummary>) -> Vec<TaskMatch> {
+    let mut matches: Vec<TaskMatch> = tasks
+        .into_iter()
+        .filter_map(|task| {
+            let kind = match_name(query, &task.name)?;
+            Some(TaskMatch { task, kind })
+        })
+        .collect();
+
+    matches.sort_by(|a, b| {
+        a.kind
+            .cmp(&b.kind)
+            .then(b.task.logging.cmp(&a.task.logging))
+            .then(b.task.last_end.cmp(&a.task.last_end))
+            .then(b.task.id.cmp(&a.task.id))
+    });
+    matches
+}
+
+fn match_name(query: &str, name: &str) -> Option<MatchKind> {
+    if name == query {
+        return Some(MatchKind::Exact);
+    }
+
+    let query = query.trim().to_lowercase();
+    let name = name.to_lowercase();
+    if query.is_empty() {
+        None
+    } else if name == query {
+        Some(MatchKind::IgnoreCase)
+    } else if name.starts_with(&query) {
+        Some(MatchKind::Prefix)
+    } else if query.split_whitespace().all(|word| name.contains(word)) {
+        Some(MatchKind::Words)
+    } else if is_subsequence(&query, &name) {
+        Some(MatchKind::Fuzzy)
+    } else {
+        None
+    }
+}
+
+// The characters of the query appear in the name in the same order,
+// as in "crev" for "Code review"
+fn is_subsequence(query: &str, name: &str) -> bool {
+    let mut chars = name.chars();
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .all(|c| chars.any(|other| other == c))
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, OptionalExtension};
+
+use crate::errors::{Error, ValueError};
+use crate::project::Project;
+use crate::repo::{Log, Task};
+use crate::storage::{missing_task, Storage, StorageLock};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS tasks (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        project TEXT,
+        tags TEXT NOT NULL DEFAULT '',
+        archived INTEGER NOT NULL DEFAULT 0,
+        is_break INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS counters (
+        name TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS logs (
+        task_id INTEGER NOT NULL REFERENCES tasks(id),
+        start TEXT NOT NULL,
+        end TEXT,
+        note TEXT
+    );
+    CREATE INDEX IF NOT EXISTS logs_task_id ON logs(task_id);
+";
+
+/// Keeps all tasks in a single SQLite database, which scales better than
+/// one file per task to tens of thousands of logs.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    connection: rusqlite::Connection,
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Value(ValueError::new(format!("database error: {}", err).as_str()))
+    }
+}
+
+impl SqliteStorage {
+    /// Opens the database, creating it if it does not exist.
+    pub fn open(path: &Path) -> Result<SqliteStorage, Error> {
+        let connection = rusqlite::Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+
+        // Databases created before tasks could be archived or breaks, and logs have notes
+        add_column(&connection, "tasks", "archived", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(&connection, "tasks", "is_break", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(&connection, "logs", "note", "TEXT")?;
+
+        Ok(SqliteStorage {
+            path: path.to_path_buf(),
+            connection,
+        })
+    }
+
+    /// Stores all the given tasks in a single transaction.
+    pub fn replace_tasks(&self, tasks: &[Task]) -> Result<(), Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        for task in tasks {
+            write_task(&transaction, task)?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    /// Never gives the ID to a new task, as when it was deleted.
+    pub(crate) fn record_id(&self, id: u32) -> Result<(), Error> {
+        record_id(&self.connection, id)
+    }
+
+    fn read_logs(&self, id: Option<u32>) -> Result<HashMap<u32, Vec<Log>>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT task_id, start, end, note FROM logs
+             WHERE ?1 IS NULL OR task_id = ?1 ORDER BY rowid",
+        )?;
+        let rows = statement.query_map(params![id], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+
+        let mut logs: HashMap<u32, Vec<Log>> = HashMap::new();
+        for row in rows {
+            let (task_id, start, end, note) = row?;
+            let log = Log {
+                start: parse_time(&start)?,
+                end: end.as_deref().map(parse_time).transpose()?,
+                note,
+            };
+            logs.entry(task_id).or_default().push(log);
+        }
+
+        Ok(logs)
+    }
+
+    fn read_tasks(&self, id: Option<u32>) -> Result<HashMap<u32, Task>, Error> {
+        let mut logs = self.read_logs(id)?;
+
+        let mut statement = self.connection.prepare(
+            "SELECT id, name, project, tags, archived, is_break FROM tasks
+             WHERE ?1 IS NULL OR id = ?1",
+        )?;
+        let rows = statement.query_map(params![id], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, bool>(4)?,
+                row.get::<_, bool>(5)?,
+            ))
+        })?;
+
+        let mut tasks = HashMap::new();
+        for row in rows {
+            let (id, name, project, tags, archived, is_break) = row?;
+            let logs = logs.remove(&id).unwrap_or_default();
+            tasks.insert(
+                id,
+                Task {
+                    id,
+                    path: self.path.clone(),
+                    name,
+                    project: project.as_deref().map(Project::parse).transpose()?,
+                    tags: tags.split_whitespace().map(|tag| tag.to_string()).collect(),
+                    archived,
+                    is_break,
+                    logging: logs.iter().any(|log| log.end.is_none()),
+                    logs,
+                },
+            );
+        }
+
+        Ok(tasks)
+    }
+}
+
+fn parse_time(raw_time: &str) -> Result<chrono::DateTime<chrono::Utc>, Error> {
+    chrono::DateTime::parse_from_rfc3339(raw_time)
+        .map(|time| time.with_timezone(&chrono::Utc))
+        .map_err(|_| Error::Value(ValueError::new(format!("invalid time '{}' in database", raw_time).as_str())))
+}
+
+fn add_column(
+    connection: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), Error> {
+    let query = format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = '{}'", table, column);
+    if !connection.prepare(&query)?.exists([])? {
+        let alter = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition);
+        connection.execute_batch(&alter)?;
+    }
+
+    Ok(())
+}
+
+// The highest ID ever given, so that the IDs of deleted tasks are not reused
+fn record_id(connection: &rusqlite::Connection, id: u32) -> Result<(), Error> {
+    connection.execute(
+        "INSERT INTO counters (name, value) VALUES ('last_id', ?1)
+         ON CONFLICT(name) DO UPDATE SET value = MAX(value, ?1)",
+        params![id],
+    )?;
+    Ok(())
+}
+
+fn write_task(connection: &rusqlite::Connection, task: &Task) -> Result<(), Error> {
+    connection.execute(
+        "INSERT INTO tasks (id, name, project, tags, archived, is_break)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(id) DO UPDATE
+         SET name = ?2, project = ?3, tags = ?4, archived = ?5, is_break = ?6",
+        params![
+            task.id,
+            task.name,
+            task.project.as_ref().map(|project| project.to_string()),
+            task.tags.join(" "),
+            task.archived,
+            task.is_break
+        ],
+    )?;
+    record_id(connection, task.id)?;
+
+    connection.execute("DELETE FROM logs WHERE task_id = ?1", params![task.id])?;
+    let mut statement =
+        connection.prepare("INSERT INTO logs (task_id, start, end, note) VALUES (?1, ?2, ?3, ?4)")?;
+    for log in task.logs.iter() {
+        statement.execute(params![
+            task.id,
+            log.start.to_rfc3339(),
+            log.end.map(|end| end.to_rfc3339()),
+            log.note
+        ])?;
+    }
+
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn list_tasks(&self) -> Result<HashMap<u32, Task>, Error> {
+        self.read_tasks(None)
+    }
+
+    fn get_task(&self, id: u32) -> Result<Task, Error> {
+        self.read_tasks(Some(id))?.remove(&id).ok_or_else(|| missing_task(id))
+    }
+
+    fn create_task_with_id(&self, id: u32, name: &str) -> Result<Task, Error> {
+        let exists = self
+            .connection
+            .query_row("SELECT 1 FROM tasks WHERE id = ?1", params![id], |_| Ok(()))
+            .optional()?
+            .is_some();
+        if exists {
+            return Err(Error::Value(ValueError::new(
+                format!("Task @{} already exists.", id).as_str(),
+            )));
+        }
+
+        let task = Task {
+            id,
+            path: self.path.clone(),
+            name: name.to_string(),
+            project: None,
+            tags: Vec::new(),
+            archived: false,
+            is_break: false,
+            logs: Vec::new(),
+            logging: false,
+        };
+        self.replace_task(&task)?;
+
+        Ok(task)
+    }
+
+    fn replace_task(&self, task: &Task) -> Result<(), Error> {
+        self.replace_tasks(std::slice::from_ref(task))
+    }
+
+    fn delete_task(&self, id: u32) -> Result<(), Error> {
+        let transaction = self.connection.unchecked_transaction()?;
+        transaction.execute("DELETE FROM logs WHERE task_id = ?1", params![id])?;
+        let deleted = transaction.execute("DELETE FROM tasks WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(missing_task(id));
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn next_id(&self) -> Result<u32, Error> {
+        let max_id: Option<u32> = self.connection.query_row(
+            "SELECT MAX(value) FROM (SELECT MAX(id) AS value FROM tasks
+             UNION ALL SELECT value FROM counters WHERE name = 'last_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(max_id.unwrap_or(0) + 1)
+    }
+
+    // SQLite only locks single transactions, read-modify-write
+    // sequences need a lock of their own
+    fn lock(&self) -> Result<StorageLock, Error> {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".lock");
+        StorageLock::file(Path::new(&path))
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use fs2::FileExt;
+
+use crate::errors::{Error, ValueError};
+use crate::repo::{Log, Repo, Task};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStorage;
+
+/// Name of the database file of profiles stored in SQLite.
+pub const SQLITE_FILE: &str = "timers.sqlite";
+
+// Where the task files go when migrating to SQLite
+const TEXT_BACKUP_DIR: &str = "text-backup";
+
+/// The ways tasks can be stored on disk.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StorageKind {
+    /// One plain text file per task, the default.
+    Text,
+    /// A single SQLite database, requires the sqlite feature.
+    Sqlite,
+}
+
+impl StorageKind {
+    pub fn parse(raw: &str) -> Result<StorageKind, Error> {
+        match raw {
+            "text" => Ok(StorageKind::Text),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            _ => Err(Error::Value(ValueError::new(
+                format!("'{}' is not a storage, use 'text' or 'sqlite'.", raw).as_str(),
+            ))),
+        }
+    }
+
+    /// The storage used by the data in the given directory.
+    pub fn detect(path: &Path) -> StorageKind {
+        if path.join(SQLITE_FILE).exists() {
+            StorageKind::Sqlite
+        } else {
+            StorageKind::Text
+        }
+    }
+}
+
+impl std::fmt::Display for StorageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StorageKind::Text => write!(f, "text"),
+            StorageKind::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// What a storage knows about a task without reading all of its logs,
+/// enough to find the task logging or the tasks logged in a time range.
+#[derive(Debug, Clone)]
+pub struct TaskSummary {
+    pub id: u32,
+    pub name: String,
+    pub first_start: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_end: Option<chrono::DateTime<chrono::Utc>>,
+    pub logging: bool,
+    pub is_break: bool,
+}
+
+impl TaskSummary {
+    pub fn of(task: &Task) -> TaskSummary {
+        TaskSummary {
+            id: task.id,
+            name: task.name.clone(),
+            first_start: task.logs.iter().map(|log| log.start).min(),
+            last_end: task.logs.iter().filter_map(|log| log.end).max(),
+            logging: task.logs.iter().any(|log| log.end.is_none()),
+            is_break: task.is_break,
+        }
+    }
+
+    /// Whether the task can have logs between start and end,
+    /// with the same bounds as `Timers::get_all_tasks_between`.
+    pub fn may_overlap(&self, start: chrono::DateTime<chrono::Utc>, end: chrono::DateTime<chrono::Utc>) -> bool {
+        match self.first_start {
+            Some(first_start) => {
+                first_start <= end && (self.logging || self.last_end.is_some_and(|last_end| last_end >= start))
+            }
+            None => false,
+        }
+    }
+}
+
+/// Advisory lock on a storage, released when dropped.
+#[derive(Debug)]
+pub struct StorageLock {
+    file: Option<std::fs::File>,
+}
+
+impl StorageLock {
+    /// Locks the given file, creating it if needed.
+    pub fn file(path: &Path) -> Result<StorageLock, Error> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        file.lock_exclusive()?;
+
+        Ok(StorageLock { file: Some(file) })
+    }
+
+    /// For storages which are not shared between processes.
+    pub fn none() -> StorageLock {
+        StorageLock { file: None }
+    }
+}
+
+impl Drop for StorageLock {
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            let _ = file.unlock();
+        }
+    }
+}
+
+/// Where tasks are kept. The provided methods are built on the required
+/// ones, so that all storages behave the same.
+pub trait Storage {
+    fn list_tasks(&self) -> Result<HashMap<u32, Task>, Error>;
+
+    fn get_task(&self, id: u32) -> Result<Task, Error>;
+
+    /// Creates an empty task with the given ID, failing if it already exists.
+    fn create_task_with_id(&self, id: u32, name: &str) -> Result<Task, Error>;
+
+    /// Stores the task as it is, without reordering its logs.
+    fn replace_task(&self, task: &Task) -> Result<(), Error>;
+
+    fn delete_task(&self, id: u32) -> Result<(), Error>;
+
+    fn next_id(&self) -> Result<u32, Error>;
+
+    /// Takes an advisory lock, which is held until the returned guard is
+    /// dropped. Used around read-modify-write sequences, so that concurrent
+    /// invocations do not overwrite each other's changes.
+    fn lock(&self) -> Result<StorageLock, Error>;
+
+    /// Summaries of all tasks. Storages should provide a faster
+    /// implementation than reading all tasks, as this one does.
+    fn list_summaries(&self) -> Result<Vec<TaskSummary>, Error> {
+        Ok(self.list_tasks()?.values().map(TaskSummary::of).collect())
+    }
+
+    /// The task in the plain text format, as edited with `timers edit`.
+    fn task_content(&self, id: u32) -> Result<String, Error> {
+        Ok(Repo::format_task(&self.get_task(id)?))
+    }
+
+    fn create_task(&self, name: &str) -> Result<Task, Error> {
+        let id = self.next_id()?;
+        self.create_task_with_id(id, name)
+    }
+
+    // Stores the task after its logs were changed in place,
+    // keeping them in chronological order
+    fn update_task(&self, task: &mut Task) -> Result<(), Error> {
+        task.logs.sort_by_key(|log| log.start);
+        task.logging = task.logs.iter().any(|log| log.end.is_none());
+
+        self.replace_task(task)
+    }
+
+    fn log_task(&self, task: &mut Task, time: chrono::DateTime<chrono::Utc>) -> Result<(), Error> {
+        task.logging = true;
+        task.logs.push(Log::new(time, None));
+
+        self.replace_task(task)
+    }
+
+    fn stop_task(&self, task: &mut Task, time: chrono::DateTime<chrono::Utc>) -> Result<(), Error> {
+        task.logging = false;
+
+        match task.logs.last_mut() {
+            Some(log) if log.end.is_none() => log.end = Some(time),
+            _ => {
+                return Err(Error::Value(ValueError::new(
+                    "Task was not started, cannot stop logging.",
+                )))
+            }
+        }
+
+        self.replace_task(task)
+    }
+}
+
+pub(crate) fn missing_task(id: u32) -> Error {
+    Error::Value(ValueError::new(format!("Task @{} does not exist.", id).as_str()))
+}
+
+pub(crate) fn open_storage(path: &Path, kind: StorageKind) -> Result<Box<dyn Storage>, Error> {
+    match kind {
+        StorageKind::Text => Ok(Box::new(Repo {
+            path: path.to_path_buf(),
+        })),
+        #[cfg(feature = "sqlite")]
+        StorageKind::Sqlite => Ok(Box::new(SqliteStorage::open(&path.join(SQLITE_FILE))?)),
+        #[cfg(not(feature = "sqlite"))]
+        StorageKind::Sqlite => Err(no_sqlite()),
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn no_sqlite() -> Error {
+    Error::Value(ValueError::new(
+        "SQLite storage is not available, timers was built without the sqlite feature.",
+    ))
+}
+
+// The data of the old storage is kept as a backup: the task files are
+// moved to the text-backup directory, the database gets a .bak extension.
+// Going back to text files removes the text-backup directory, whose tasks
+// are in the database, and the lock file of the old storage is removed
+pub(crate) fn migrate(path: &Path, to: StorageKind) -> Result<usize, Error> {
+    let from = StorageKind::detect(path);
+    if from == to {
+        return Err(Error::Value(ValueError::new(
+            format!("The profile already uses {} storage.", to).as_str(),
+        )));
+    }
+
+    let source = open_storage(path, from)?;
+    let lock = source.lock()?;
+
+    let repo = Repo {
+        path: path.to_path_buf(),
+    };
+    let mut tasks: Vec<Task> = source.list_tasks()?.into_values().collect();
+    if from == StorageKind::Text && tasks.len() != repo.task_files()?.len() {
+        return Err(Error::Value(ValueError::new(
+            "Some task files cannot be read, repair them with 'timers fsck' first.",
+        )));
+    }
+    tasks.sort_by_key(|task| task.id);
+    // Also covers the tasks deleted since
+    let last_id = source.next_id()? - 1;
+
+    match to {
+        #[cfg(feature = "sqlite")]
+        StorageKind::Sqlite => {
+            let temp_path = path.join(format!("{}.tmp", SQLITE_FILE));
+            if temp_path.exists() {
+                std::fs::remove_file(&temp_path)?;
+            }
+            let target = SqliteStorage::open(&temp_path)?;
+            target.replace_tasks(&tasks)?;
+            target.record_id(last_id)?;
+            drop(target);
+            std::fs::rename(&temp_path, path.join(SQLITE_FILE))?;
+
+            let backup_path = path.join(TEXT_BACKUP_DIR);
+            std::fs::create_dir_all(&backup_path)?;
+            for (_, file) in repo.task_files()? {
+                std::fs::rename(&file, backup_path.join(file.file_name().unwrap()))?;
+            }
+            drop(lock);
+            remove_if_exists(&path.join(crate::repo::LOCK_FILE))?;
+        }
+        #[cfg(not(feature = "sqlite"))]
+        StorageKind::Sqlite => return Err(no_sqlite()),
+        StorageKind::Text => {
+            if !repo.task_files()?.is_empty() {
+                return Err(Error::Value(ValueError::new(
+                    format!("There are already task files in {}.", path.display()).as_str(),
+                )));
+            }
+
+            for task in tasks.iter() {
+                repo.replace_task(task)?;
+            }
+            repo.record_id(last_id)?;
+            std::fs::rename(path.join(SQLITE_FILE), path.join(format!("{}.bak", SQLITE_FILE)))?;
+            if path.join(TEXT_BACKUP_DIR).exists() {
+                std::fs::remove_dir_all(path.join(TEXT_BACKUP_DIR))?;
+            }
+            drop(lock);
+            remove_if_exists(&path.join(format!("{}.lock", SQLITE_FILE)))?;
+        }
+    }
+
+    Ok(tasks.len())
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(Error::Io(err)),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,135 @@
+use colored::*;
+
+use crate::output;
+use crate::util::{is_interactive, parse_task_id, print_status, resolve_task, user_input};
+
+pub fn rename_command(matches: &clap::ArgMatches) {
+    let task_id = match resolve_task(matches.value_of("TASK").unwrap()) {
+        Some(task_id) => task_id,
+        None => return,
+    };
+
+    match timers::rename_task(task_id, matches.value_of("NAME").unwrap()) {
+        Ok(task) => print_status(&task),
+        Err(err) => output::fail_with("Error renaming task", &err),
+    }
+}
+
+pub fn archive_command(matches: &clap::ArgMatches) {
+    let task_id = match resolve_task(matches.value_of("TASK").unwrap()) {
+        Some(task_id) => task_id,
+        None => return,
+    };
+
+    let archived = !matches.is_present("undo");
+    match timers::set_task_archived(task_id, archived) {
+        Ok(task) => {
+            output::note(&format!(
+                "{} task @{}: {}",
+                if archived { "Archived" } else { "Unarchived" },
+                task.id,
+                task.name
+            ));
+            if !output::is_text() {
+                print_status(&task);
+            }
+        }
+        Err(err) => output::fail_with("Error archiving task", &err),
+    }
+}
+
+pub fn delete_command(matches: &clap::ArgMatches) {
+    let task_id = match resolve_task(matches.value_of("TASK").unwrap()) {
+        Some(task_id) => task_id,
+        None => return,
+    };
+
+    if !matches.is_present("yes") && !confirm_delete(task_id) {
+        return;
+    }
+
+    match timers::delete_task(task_id) {
+        Ok(Some(task)) => output::note(&format!(
+            "Deleted task @{}: {}, restore it with 'timers restore @{}'",
+            task.id, task.name, task.id
+        )),
+        Ok(None) => output::note(&format!(
+            "Deleted task @{}, restore it with 'timers restore @{}'",
+            task_id, task_id
+        )),
+        Err(err) => output::fail_with("Error deleting task", &err),
+    }
+}
+
+fn confirm_delete(task_id: u32) -> bool {
+    let task = match timers::get_task(task_id) {
+        Ok(task) => format!(
+            "task {} {} with {} logs",
+            format!("@{}:", task.id).yellow().bold(),
+            task.name.red().bold(),
+            task.logs.len()
+        ),
+        // Corrupt tasks can be deleted too
+        Err(timers::Error::Corrupt { .. }) => format!("task @{}", task_id),
+        Err(err) => {
+            output::fail_with("Error reading task", &err);
+            return false;
+        }
+    };
+
+    if !is_interactive() {
+        output::usage_error(&format!(
+            "Cannot ask whether to delete {} without a terminal, use --yes to delete it.",
+            task
+        ));
+        return false;
+    }
+
+    let answer = user_input(&format!("Delete {}? [y/n] ", task));
+    if answer.trim() == "y" || answer.trim() == "yes" {
+        true
+    } else {
+        output::fail(output::EXIT_FAILURE, "aborting");
+        false
+    }
+}
+
+pub fn restore_command(matches: &clap::ArgMatches) {
+    // Deleted tasks can only be given by ID, as names are looked up among existing tasks
+    let task_id = match matches.value_of("TASK") {
+        Some(raw_task) => match parse_task_id(raw_task) {
+            Some(task_id) => task_id,
+            None => return,
+        },
+        None => return print_trash(),
+    };
+
+    match timers::restore_task(task_id) {
+        Ok(task) => print_status(&task),
+        Err(err) => output::fail_with("Error restoring task", &err),
+    }
+}
+
+fn print_trash() {
+    let tasks = match timers::trashed_tasks() {
+        Ok(tasks) => tasks,
+        Err(err) => return output::fail_with("Error reading the trash", &err),
+    };
+
+    if !output::is_text() {
+        let records: Vec<output::Record> = tasks.iter().map(output::task_record).collect();
+        return output::print_records(&records);
+    }
+
+    if tasks.is_empty() {
+        return println!("The trash is empty.");
+    }
+    for task in tasks {
+        println!(
+            "{} {} [{}]",
+            format!("@{}:", task.id).yellow().bold(),
+            task.name,
+            timers::format_duration(task.duration())
+        );
+    }
+}
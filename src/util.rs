@@ -1,15 +1,42 @@
 use std::io::prelude::*;
+use std::io::IsTerminal;
 use std::num::{ParseIntError, ParseFloatError};
 use std::ops::Add;
+use std::sync::OnceLock;
 
 use colored::*;
-use chrono;
-use chrono::{Timelike, TimeZone};
+use chrono::{Datelike, Timelike, TimeZone};
 use chrono::offset::LocalResult::Single;
 
+use crate::output;
+
+// Loaded once, an invalid configuration file is reported and ignored
+pub fn config() -> &'static timers::Config {
+    static CONFIG: OnceLock<timers::Config> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        timers::Config::load().unwrap_or_else(|err| {
+            eprintln!("Warning: using the default configuration. {}", err);
+            timers::Config::default()
+        })
+    })
+}
+
+/// Whether questions can be asked: standard input is a terminal and
+/// `TIMERS_NONINTERACTIVE` is not set.
+pub fn is_interactive() -> bool {
+    let disabled = std::env::var("TIMERS_NONINTERACTIVE")
+        .is_ok_and(|value| !value.is_empty() && value != "0");
+    !disabled && std::io::stdin().is_terminal()
+}
+
+// The prompt goes with the messages for humans, see output::note
 pub fn user_input(prompt: &str) -> String {
-    print!("{}", prompt);
-    std::io::stdout().flush().unwrap();
+    if output::is_text() {
+        print!("{}", prompt);
+        std::io::stdout().flush().unwrap();
+    } else {
+        eprint!("{}", prompt);
+    }
 
     let mut answer = String::new();
     std::io::stdin().read_line(&mut answer).unwrap();
@@ -18,32 +45,266 @@ pub fn user_input(prompt: &str) -> String {
 }
 
 pub fn print_status(task: &timers::Task) {
+    if !output::is_text() {
+        return output::print_record(&output::task_record(task));
+    }
+
+    let tags = if task.tags.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", format_tags(task).cyan())
+    };
+    let project = match &task.project {
+        Some(project) => format!("\nproject: {}", project.to_string().bold()),
+        None => String::new(),
+    };
+
     println!(
-        "{} {}\nstatus: {}\ntime: {}",
+        "{} {}{}{}\nstatus: {}\ntime: {}",
         format!("@{}:", task.id).yellow().bold(),
         task.name.red().bold(),
+        tags,
+        project,
         task.status_text().bold(),
         timers::format_duration(task.duration()).bold()
     );
 }
 
+pub fn parse_task_id(raw_task: &str) -> Option<u32> {
+    match raw_task.trim_start_matches('@').parse::<u32>() {
+        Ok(task_id) => Some(task_id),
+        Err(_) => {
+            output::usage_error(&format!("'{}' is an invalid task ID", raw_task));
+            None
+        }
+    }
+}
+
+/// A task chosen among the ones matching a name.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Picked {
+    Task(u32),
+    New,
+}
+
+/// The existing task given as @ID, or ID as before, or by its name. A name is looked up
+/// ignoring case, then approximately, asking which task was meant unless the
+/// name is the one of a task.
+pub fn resolve_task(raw_task: &str) -> Option<u32> {
+    if raw_task.starts_with('@') {
+        return parse_task_id(raw_task);
+    }
+    if let Some(task_id) = bare_task_id(raw_task, |task_id| timers::get_task(task_id).is_ok()) {
+        return Some(task_id);
+    }
+
+    let matches = match timers::find_tasks(raw_task) {
+        Ok(matches) => matches,
+        Err(err) => {
+            output::fail_with("Error finding task", &err);
+            return None;
+        }
+    };
+
+    match matches.first() {
+        None => {
+            output::fail(output::EXIT_FAILURE, &format!("No task matches '{}'", raw_task));
+            None
+        }
+        Some(best) if best.kind.is_exact() => Some(best.task.id),
+        Some(_) => match pick_task(raw_task, &matches, false)? {
+            Picked::Task(task_id) => Some(task_id),
+            Picked::New => None,
+        },
+    }
+}
+
+// A number without @ is an ID only if there is a task with that ID,
+// otherwise it is the name of a task, such as a year or a ticket number
+fn bare_task_id(raw_task: &str, exists: impl FnOnce(u32) -> bool) -> Option<u32> {
+    raw_task.parse::<u32>().ok().filter(|task_id| exists(*task_id))
+}
+
+// A name continues the task with that name if there is one, so that the same
+// task is not created again. Other similar names are only offered when asking
+// is possible, as the name may well be meant for a new task. A task given by
+// ID must exist, so that nothing else is done for a mistyped one.
+pub fn log_target(task: &str, new: bool) -> Option<Picked> {
+    if new {
+        return Some(Picked::New);
+    }
+    if task.starts_with('@') {
+        let task_id = parse_task_id(task)?;
+        return match timers::get_task(task_id) {
+            Ok(_) => Some(Picked::Task(task_id)),
+            Err(err) => {
+                output::fail_with("Error finding task", &err);
+                None
+            }
+        };
+    }
+
+    let matches = match timers::find_tasks(task) {
+        Ok(matches) => matches,
+        Err(err) => {
+            output::fail_with("Error finding task", &err);
+            return None;
+        }
+    };
+
+    match matches.first() {
+        Some(best) if best.kind.is_exact() => Some(Picked::Task(best.task.id)),
+        Some(_) if is_interactive() => pick_task(task, &matches, true),
+        _ => Some(Picked::New),
+    }
+}
+
+/// Asks which of the matching tasks was meant, or whether to create a new
+/// task if `new` is set. Fails without a terminal to ask on.
+pub fn pick_task(raw_task: &str, matches: &[timers::TaskMatch], new: bool) -> Option<Picked> {
+    // The rest would hardly be what was meant
+    let matches = &matches[..matches.len().min(9)];
+    let candidates: Vec<String> = matches
+        .iter()
+        .map(|candidate| {
+            let task = &candidate.task;
+            match task.last_end {
+                _ if task.logging => format!("@{}: {} (logging)", task.id, task.name),
+                Some(last_end) => format!(
+                    "@{}: {} (last logged {})",
+                    task.id,
+                    task.name,
+                    last_end.with_timezone(&chrono::Local).format("%Y-%m-%d")
+                ),
+                None => format!("@{}: {}", task.id, task.name),
+            }
+        })
+        .collect();
+
+    if !is_interactive() {
+        output::fail(
+            output::EXIT_FAILURE,
+            &format!(
+                "'{}' is not the name of a task, use the ID of the task meant: {}",
+                raw_task,
+                candidates.join(", ")
+            ),
+        );
+        return None;
+    }
+
+    output::note(&format!("'{}' is not the name of a task, it matches:", raw_task));
+    for (index, candidate) in candidates.iter().enumerate() {
+        output::note(&format!("  {}) {}", index + 1, candidate));
+    }
+    let prompt = if new {
+        format!("Which task? [1-{}, or n for a new task] ", matches.len())
+    } else {
+        format!("Which task? [1-{}] ", matches.len())
+    };
+    let answer = user_input(&prompt);
+
+    match answer.trim() {
+        "n" | "new" if new => Some(Picked::New),
+        choice => match choice.parse::<usize>() {
+            Ok(index) if index >= 1 && index <= matches.len() => {
+                Some(Picked::Task(matches[index - 1].task.id))
+            }
+            _ => {
+                output::fail(output::EXIT_FAILURE, "aborting");
+                None
+            }
+        },
+    }
+}
+
+// Logs are referenced as @TASK#INDEX, with 1-based indices,
+// the task can also be given by name as for resolve_task
+pub fn parse_log_ref(raw_log: &str) -> Option<(u32, usize)> {
+    let parsed = raw_log.rsplit_once('#').and_then(|(raw_task, index)| {
+        match index.parse::<usize>() {
+            Ok(index) if index > 0 && !raw_task.is_empty() => Some((raw_task, index)),
+            _ => None,
+        }
+    });
+
+    match parsed {
+        Some((raw_task, index)) => Some((resolve_task(raw_task)?, index)),
+        None => {
+            output::usage_error(&format!(
+                "'{}' is an invalid log reference, expected @TASK#INDEX",
+                raw_log
+            ));
+            None
+        }
+    }
+}
+
+pub fn format_log(log: &timers::Log) -> String {
+    let start = log.start.with_timezone(&chrono::Local);
+    let end = log.end.map(|end| end.with_timezone(&chrono::Local));
+    let time_format = config().time_format();
+    let datetime_format = format!("%a %Y-%m-%d {}", time_format);
+
+    match end {
+        Some(end) if end.date_naive() == start.date_naive() => format!(
+            "{} -> {}",
+            start.format(&datetime_format),
+            end.format(time_format),
+        ),
+        Some(end) => format!(
+            "{} -> {}",
+            start.format(&datetime_format),
+            end.format(&datetime_format),
+        ),
+        None => format!("{} -> now", start.format(&datetime_format)),
+    }
+}
+
+pub fn format_project(task: &timers::Task) -> String {
+    match &task.project {
+        Some(project) => project.to_string(),
+        None => String::new(),
+    }
+}
+
+pub fn format_tags(task: &timers::Task) -> String {
+    task.tags.iter().map(|tag| format!("+{}", tag)).collect::<Vec<_>>().join(" ")
+}
+
+// Tags are written as +tag on the command line
+pub fn parse_tags<'a, I: Iterator<Item = &'a str>>(raw_tags: I) -> Option<Vec<String>> {
+    let mut tags = Vec::new();
+    for raw_tag in raw_tags {
+        let tag = raw_tag.trim_start_matches('+');
+        if tag.is_empty() || tag.contains(char::is_whitespace) {
+            output::usage_error(&format!("'{}' is not a valid tag", raw_tag));
+            return None;
+        }
+        tags.push(tag.to_string());
+    }
+
+    Some(tags)
+}
+
+pub fn task_filter(matches: &clap::ArgMatches) -> Option<timers::TaskFilter> {
+    Some(timers::TaskFilter {
+        tags: parse_tags(matches.values_of("tag").into_iter().flatten())?,
+        not_tags: parse_tags(matches.values_of("not-tag").into_iter().flatten())?,
+    })
+}
+
 pub fn parse_time(raw_time: &str) -> Option<chrono::DateTime<chrono::Utc>> {
     if raw_time.starts_with("y") {
         let stripped_time = raw_time.get(1..).unwrap();
-        return match parse_time(stripped_time) {
-            Some(datetime) => Some(datetime - chrono::Duration::days(1)),
-            None => None
-        }
+        return parse_time(stripped_time).map(|datetime| datetime - chrono::Duration::days(1));
     }
 
     if raw_time.starts_with("-") || raw_time.starts_with("+") {
         let sign = raw_time.get(..1).unwrap();
         let stripped_time = raw_time.get(1..).unwrap();
-        return match parse_duration(stripped_time) {
-            Some(duration) =>
-                Some(chrono::Utc::now() + if sign == "+" {duration} else {-duration}),
-            None => None
-        }
+        return parse_duration(stripped_time)
+            .map(|duration| chrono::Utc::now() + if sign == "+" {duration} else {-duration});
     }
 
     if let Some(datetime) = try_parse_time(raw_time, "%H:%M") {
@@ -66,10 +327,31 @@ pub fn parse_time(raw_time: &str) -> Option<chrono::DateTime<chrono::Utc>> {
         return Some(datetime)
     }
 
-    println!("Time format '{}' not understood", raw_time);
+    output::usage_error(&format!("Time format '{}' not understood", raw_time));
     None
 }
 
+// Times of the day refer to the given date instead of today,
+// so that past logs can be changed with just a time
+pub fn parse_time_on(raw_time: &str, date: chrono::NaiveDate) -> Option<chrono::DateTime<chrono::Utc>> {
+    for fmt in ["%H:%M", "%H:%M:%S"].iter() {
+        if let Ok(parsed_time) = chrono::NaiveTime::parse_from_str(raw_time, fmt) {
+            return match chrono::Local.from_local_datetime(&date.and_time(parsed_time)) {
+                Single(datetime) => Some(datetime.with_timezone(&chrono::Utc)),
+                _ => {
+                    output::usage_error(&format!(
+                        "Time '{}' is ambiguous or does not exist on {}",
+                        raw_time, date
+                    ));
+                    None
+                }
+            };
+        }
+    }
+
+    parse_time(raw_time)
+}
+
 pub fn try_parse_time(raw_time: &str, fmt: &str) -> Option<chrono::DateTime<chrono::Utc>> {
     match chrono::NaiveTime::parse_from_str(raw_time, fmt) {
         Ok(parsed_time) => {
@@ -99,22 +381,92 @@ pub fn try_parse_datetime(raw_datetime: &str, fmt: &str) -> Option<chrono::DateT
 
 pub fn try_parse_date(raw_date: &str, fmt: &str) -> Option<chrono::DateTime<chrono::Utc>> {
     match chrono::NaiveDate::parse_from_str(raw_date, fmt) {
-        Ok(parsed_date) => {
-            match chrono::Local.from_local_date(&parsed_date) {
-                Single(date) => Some(
-                    date
-                        .and_hms(0, 0, 0)
-                        .with_timezone(&chrono::Utc)
-                ),
-                _ => None,
-            }
-        }
+        Ok(parsed_date) => Some(day_start(parsed_date)),
         Err(_) => None,
     }
 }
 
+// Local midnight may be skipped or repeated when the clocks change,
+// the day then starts at the earliest valid local time
+pub fn day_start(date: chrono::NaiveDate) -> chrono::DateTime<chrono::Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    (0..24 * 4)
+        .map(|quarter| midnight + chrono::Duration::minutes(15 * quarter))
+        .find_map(|time| chrono::Local.from_local_datetime(&time).earliest())
+        .map(|datetime| datetime.with_timezone(&chrono::Utc))
+        .unwrap_or_else(|| chrono::Utc.from_utc_datetime(&midnight))
+}
+
+pub fn week_start_date(date: chrono::NaiveDate) -> chrono::NaiveDate {
+    let days_since_start = (date.weekday().num_days_from_monday() + 7
+        - config().week_start().num_days_from_monday())
+        % 7;
+    date - chrono::Duration::days(days_since_start as i64)
+}
+
+// Periods are given either relative to the current one, as in -1,
+// or as a date in the period
+pub fn parse_offset(raw: &str) -> Option<i64> {
+    if raw == "0" || raw.starts_with('-') || raw.starts_with('+') {
+        parse_int(raw).ok()
+    } else {
+        None
+    }
+}
+
+pub fn parse_week(raw_week: &str) -> Option<chrono::NaiveDate> {
+    let today = chrono::Local::now().date_naive();
+    if let Some(offset) = parse_offset(raw_week) {
+        let week = chrono::Duration::try_weeks(offset)
+            .and_then(|offset| week_start_date(today).checked_add_signed(offset));
+        if week.is_none() {
+            output::usage_error(&format!("Week offset '{}' is out of range", raw_week));
+        }
+        return week;
+    }
+
+    match chrono::NaiveDate::parse_from_str(raw_week, "%Y-%m-%d") {
+        Ok(date) => Some(week_start_date(date)),
+        Err(_) => {
+            output::usage_error(&format!(
+                "Week '{}' not understood, use an offset such as -1 or a date",
+                raw_week
+            ));
+            None
+        }
+    }
+}
+
+pub fn parse_day(raw_day: &str) -> Option<chrono::NaiveDate> {
+    let today = chrono::Local::now().date_naive();
+    match raw_day {
+        "today" => return Some(today),
+        "yesterday" => return Some(today - chrono::Duration::days(1)),
+        _ => {}
+    }
+    if let Some(offset) = parse_offset(raw_day) {
+        let day = chrono::Duration::try_days(offset)
+            .and_then(|offset| today.checked_add_signed(offset));
+        if day.is_none() {
+            output::usage_error(&format!("Day offset '{}' is out of range", raw_day));
+        }
+        return day;
+    }
+
+    match chrono::NaiveDate::parse_from_str(raw_day, "%Y-%m-%d") {
+        Ok(date) => Some(date),
+        Err(_) => {
+            output::usage_error(&format!(
+                "Day '{}' not understood, use today, yesterday, an offset such as -1 or a date",
+                raw_day
+            ));
+            None
+        }
+    }
+}
+
 pub fn parse_duration(raw_duration: &str) -> Option<chrono::Duration> {
-    return if raw_duration.to_string().contains(":") {
+    if raw_duration.to_string().contains(":") {
         let split: Vec<&str> = raw_duration.splitn(2, ":").collect();
         let raw_hours = split[0];
         let raw_minutes = split[1];
@@ -123,7 +475,7 @@ pub fn parse_duration(raw_duration: &str) -> Option<chrono::Duration> {
         match parse_int(raw_hours) {
             Ok(hours) => duration = duration.add(chrono::Duration::hours(hours)),
             Err(_) => {
-                println!("Duration format '{}' not understood", raw_duration);
+                output::usage_error(&format!("Duration format '{}' not understood", raw_duration));
                 return None;
             },
         }
@@ -131,7 +483,7 @@ pub fn parse_duration(raw_duration: &str) -> Option<chrono::Duration> {
         match parse_int(raw_minutes) {
             Ok(minutes) => duration = duration.add(chrono::Duration::minutes(minutes)),
             Err(_) => {
-                println!("Duration format '{}' not understood", raw_duration);
+                output::usage_error(&format!("Duration format '{}' not understood", raw_duration));
                 return None;
             },
         }
@@ -141,7 +493,7 @@ pub fn parse_duration(raw_duration: &str) -> Option<chrono::Duration> {
         match parse_int(raw_duration) {
             Ok(minutes) => Some(chrono::Duration::minutes(minutes)),
             Err(_) => {
-                println!("Duration format '{}' not understood", raw_duration);
+                output::usage_error(&format!("Duration format '{}' not understood", raw_duration));
                 None
             },
         }
@@ -149,9 +501,29 @@ pub fn parse_duration(raw_duration: &str) -> Option<chrono::Duration> {
 }
 
 pub fn parse_int(text: &str) -> Result<i64, ParseIntError> {
-    Ok(text.trim().parse::<i64>()?)
+    text.trim().parse::<i64>()
 }
 
 pub fn parse_float(text: &str) -> Result<f64, ParseFloatError> {
-    Ok(text.trim().parse::<f64>()?)
-}
\ No newline at end of file
+    text.trim().parse::<f64>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day_offsets_out_of_range_are_rejected() {
+        let today = chrono::Local::now().date_naive();
+        assert_eq!(parse_day("-1"), today.pred_opt());
+        assert_eq!(parse_day("+100000000"), None);
+        assert_eq!(parse_day("-9223372036854775808"), None);
+    }
+
+    #[test]
+    fn numbers_are_names_unless_a_task_has_that_id() {
+        assert_eq!(bare_task_id("12", |task_id| task_id == 12), Some(12));
+        assert_eq!(bare_task_id("4411", |task_id| task_id == 12), None);
+        assert_eq!(bare_task_id("Write", |_| true), None);
+    }
+}
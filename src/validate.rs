@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+
+use crate::errors::{Error, ValueError};
+use crate::repo::{Log, Task};
+
+fn format_time(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+fn invalid(description: String) -> Error {
+    Error::Value(ValueError::new(description.as_str()))
+}
+
+pub fn describe_log(log: &Log) -> String {
+    match log.end {
+        Some(end) => format!("from {} to {}", format_time(log.start), format_time(end)),
+        None => format!("from {} to now", format_time(log.start)),
+    }
+}
+
+/// Checks that the logs of the task are consistent, and that they
+/// do not overlap the logs of any other task.
+pub fn check_task(task: &Task, tasks: &HashMap<u32, Task>) -> Result<(), Error> {
+    for (i, log) in task.logs.iter().enumerate() {
+        match log.end {
+            Some(end) if end < log.start => {
+                return Err(invalid(format!(
+                    "Log #{} of @{} ends before it starts.",
+                    i + 1,
+                    task.id
+                )))
+            }
+            None if i + 1 != task.logs.len() => {
+                return Err(invalid(format!(
+                    "Log #{} of @{} has no end, only the last log can be open.",
+                    i + 1,
+                    task.id
+                )))
+            }
+            _ => {}
+        }
+
+        if let Some(previous) = i.checked_sub(1).map(|j| &task.logs[j]) {
+            if previous.start > log.start {
+                return Err(invalid(format!(
+                    "Log #{} of @{} starts before the previous one.",
+                    i + 1,
+                    task.id
+                )));
+            }
+        }
+
+        for (j, other) in task.logs.iter().enumerate().skip(i + 1) {
+            if log.overlaps(other) {
+                return Err(invalid(format!(
+                    "Logs #{} and #{} of @{} overlap.",
+                    i + 1,
+                    j + 1,
+                    task.id
+                )));
+            }
+        }
+    }
+
+    for other_task in tasks.values().filter(|other_task| other_task.id != task.id) {
+        for log in task.logs.iter() {
+            if let Some(other) = other_task.logs.iter().find(|other| other.overlaps(log)) {
+                return Err(invalid(format!(
+                    "The log {} overlaps a log of @{} {}.",
+                    describe_log(log),
+                    other_task.id,
+                    describe_log(other),
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}